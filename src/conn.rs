@@ -1,26 +1,255 @@
-use crate::subsonic::{self, Id, SubsonicData, SubsonicResponse};
+use std::{collections::HashSet, io::Read, sync::OnceLock, time::Duration};
 
-use color_eyre::Result;
+use crate::subsonic::{self, Id, JsonEnvelope, SubsonicData, SubsonicResponse};
+
+use chrono::{DateTime, Utc};
+use color_eyre::{eyre::bail, Result};
 use md5::Digest;
 use secrecy::{ExposeSecret, Secret};
-use ureq::Request;
+use serde::{Deserialize, Serialize};
 
 const SUBSONIC_API_VERSION: &str = "1.16.1";
 const SUBSONIC_CLIENT_NAME: &str = "navicon";
+/// Timeouts keeping an unreachable server from blocking the threads waiting for it.
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+const READ_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// The HTTP agent shared by all connections, reusing their sockets.
+fn agent() -> &'static ureq::Agent {
+    static AGENT: OnceLock<ureq::Agent> = OnceLock::new();
+    AGENT.get_or_init(|| {
+        ureq::AgentBuilder::new()
+            .timeout_connect(CONNECT_TIMEOUT)
+            .timeout_read(READ_TIMEOUT)
+            .build()
+    })
+}
 
+#[derive(Clone)]
 pub struct Connection {
     url: String,
     user: String,
     password: Secret<String>,
+    auth_mode: AuthMode,
+    format: ResponseFormat,
+    // names of the OpenSubsonic extensions supported by the server
+    extensions: HashSet<String>,
+}
+
+/// Optional OpenSubsonic extensions used by the client.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Extension {
+    /// structured lyrics via `getLyricsBySongId`
+    SongLyrics,
+    /// `timeOffset` for transcoded streams
+    TranscodeOffset,
+    /// parameters as POST form data instead of in the URL
+    FormPost,
+}
+
+impl Extension {
+    pub const ALL: [Extension; 3] = [
+        Extension::SongLyrics,
+        Extension::TranscodeOffset,
+        Extension::FormPost,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Extension::SongLyrics => "songLyrics",
+            Extension::TranscodeOffset => "transcodeOffset",
+            Extension::FormPost => "formPost",
+        }
+    }
+}
+
+/// The server software, as reported in the responses of OpenSubsonic servers.
+pub struct ServerInfo {
+    pub version: String,
+    pub open_subsonic: bool,
+    pub server_type: Option<String>,
+    pub server_version: Option<String>,
+}
+
+impl std::fmt::Display for ServerInfo {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if let Some(server_type) = &self.server_type {
+            write!(f, "{}", server_type)?;
+            if let Some(server_version) = &self.server_version {
+                write!(f, " {}", server_version)?;
+            }
+            write!(f, ", ")?;
+        }
+        if self.open_subsonic {
+            write!(f, "OpenSubsonic")
+        } else {
+            write!(f, "Subsonic API {}", self.version)
+        }
+    }
+}
+
+/// How requests are authenticated.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum AuthMode {
+    /// salted MD5 token (`t`, `s`), the default since API version 1.13.0
+    Token,
+    /// hex encoded password (`p=enc:...`), for servers which cannot verify tokens, e.g. with LDAP
+    Password,
+    /// OpenSubsonic API key (`apiKey`), the password is used as the key
+    ApiKey,
+}
+
+impl std::str::FromStr for AuthMode {
+    type Err = color_eyre::Report;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "token" => Ok(AuthMode::Token),
+            "password" => Ok(AuthMode::Password),
+            "api_key" => Ok(AuthMode::ApiKey),
+            _ => bail!(
+                "unknown auth mode `{}`, expected `token`, `password` or `api_key`",
+                s
+            ),
+        }
+    }
+}
+
+/// Format of the responses requested from the server.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ResponseFormat {
+    Json,
+    /// the original format, for servers with broken JSON support
+    Xml,
+}
+
+impl std::str::FromStr for ResponseFormat {
+    type Err = color_eyre::Report;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "json" => Ok(ResponseFormat::Json),
+            "xml" => Ok(ResponseFormat::Xml),
+            _ => bail!("unknown response format `{}`, expected `json` or `xml`", s),
+        }
+    }
+}
+
+/// Selection and order of the albums returned by `getAlbumList2`.
+#[derive(PartialEq, Eq, Hash, Clone, Debug, Serialize, Deserialize)]
+pub enum AlbumListType {
+    Random,
+    Newest,
+    Highest,
+    Frequent,
+    Recent,
+    AlphabeticalByName,
+    AlphabeticalByArtist,
+    Starred,
+    /// albums released in the range of years, inclusive
+    ByYear(u32, u32),
+    ByGenre(String),
+}
+
+impl AlbumListType {
+    fn params(&self) -> Vec<(&'static str, String)> {
+        let list_type = match self {
+            AlbumListType::Random => "random",
+            AlbumListType::Newest => "newest",
+            AlbumListType::Highest => "highest",
+            AlbumListType::Frequent => "frequent",
+            AlbumListType::Recent => "recent",
+            AlbumListType::AlphabeticalByName => "alphabeticalByName",
+            AlbumListType::AlphabeticalByArtist => "alphabeticalByArtist",
+            AlbumListType::Starred => "starred",
+            AlbumListType::ByYear(from, to) => {
+                return vec![
+                    ("type", "byYear".into()),
+                    ("fromYear", from.to_string()),
+                    ("toYear", to.to_string()),
+                ]
+            }
+            AlbumListType::ByGenre(genre) => {
+                return vec![("type", "byGenre".into()), ("genre", genre.clone())]
+            }
+        };
+        vec![("type", list_type.into())]
+    }
+}
+
+impl std::fmt::Display for AlbumListType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AlbumListType::Random => write!(f, "Random albums"),
+            AlbumListType::Newest => write!(f, "Recently added"),
+            AlbumListType::Highest => write!(f, "Top rated"),
+            AlbumListType::Frequent => write!(f, "Most played"),
+            AlbumListType::Recent => write!(f, "Recently played"),
+            AlbumListType::AlphabeticalByName => write!(f, "Albums by name"),
+            AlbumListType::AlphabeticalByArtist => write!(f, "Albums by artist"),
+            AlbumListType::Starred => write!(f, "Starred albums"),
+            AlbumListType::ByYear(from, to) => write!(f, "Albums from {}–{}", from, to),
+            AlbumListType::ByGenre(genre) => write!(f, "{} albums", genre),
+        }
+    }
+}
+
+/// An item to star or unstar.
+pub enum StarTarget<'a> {
+    Artist(&'a Id),
+    Album(&'a Id),
+    Song(&'a Id),
 }
 
 impl Connection {
-    pub fn new(url: String, user: String, password: String) -> Connection {
+    pub fn new(
+        url: String,
+        user: String,
+        password: Secret<String>,
+        auth_mode: AuthMode,
+        format: ResponseFormat,
+    ) -> Connection {
         Connection {
             url,
             user,
-            password: password.into(),
+            password,
+            auth_mode,
+            format,
+            extensions: HashSet::new(),
+        }
+    }
+
+    /// Check the connection and discover the OpenSubsonic extensions supported by the server.
+    pub fn connect(&mut self) -> Result<ServerInfo> {
+        self.extensions.clear();
+        let resp = ApiRequest::build(self, "ping").call()?;
+        if resp.open_subsonic {
+            let extensions = ApiRequest::build(self, "getOpenSubsonicExtensions").call()?;
+            if let Some(SubsonicData::OpenSubsonicExtensions(extensions)) = extensions.data {
+                self.extensions = extensions
+                    .into_iter()
+                    .map(|extension| extension.name)
+                    .collect();
+            }
         }
+        Ok(ServerInfo {
+            version: resp.version,
+            open_subsonic: resp.open_subsonic,
+            server_type: resp.server_type,
+            server_version: resp.server_version,
+        })
+    }
+
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    pub fn user(&self) -> &str {
+        &self.user
+    }
+
+    pub fn supports(&self, extension: Extension) -> bool {
+        self.extensions.contains(extension.name())
     }
 
     pub fn ping(&self) -> Result<bool> {
@@ -29,6 +258,7 @@ impl Connection {
         Ok(resp.status == "ok")
     }
 
+    #[allow(dead_code)]
     pub fn get_license(&self) -> Result<SubsonicResponse> {
         let req = ApiRequest::build(self, "getLicense");
         req.call()
@@ -39,8 +269,27 @@ impl Connection {
         req.call()
     }
 
-    pub fn get_artists(&self) -> Result<SubsonicResponse> {
-        let req = ApiRequest::build(self, "getArtists");
+    pub fn get_indexes(
+        &self,
+        if_modified_since: Option<u64>,
+        music_folder_id: Option<&Id>,
+    ) -> Result<SubsonicResponse> {
+        let mut req = ApiRequest::build(self, "getIndexes")
+            .optional_param("musicFolderId", music_folder_id.map(String::as_str));
+        if let Some(if_modified_since) = if_modified_since {
+            req = req.param("ifModifiedSince", &if_modified_since.to_string());
+        }
+        req.call()
+    }
+
+    pub fn get_genres(&self) -> Result<SubsonicResponse> {
+        let req = ApiRequest::build(self, "getGenres");
+        req.call()
+    }
+
+    pub fn get_artists(&self, music_folder_id: Option<&Id>) -> Result<SubsonicResponse> {
+        let req = ApiRequest::build(self, "getArtists")
+            .optional_param("musicFolderId", music_folder_id.map(String::as_str));
         req.call()
     }
 
@@ -53,10 +302,255 @@ impl Connection {
         let req = ApiRequest::build(self, "getAlbum").param("id", id);
         req.call()
     }
+
+    /// A page of the album list, starting at `offset`. At most 500 albums are returned.
+    pub fn get_album_list2(
+        &self,
+        list_type: &AlbumListType,
+        size: usize,
+        offset: usize,
+        music_folder_id: Option<&Id>,
+    ) -> Result<SubsonicResponse> {
+        let mut req = ApiRequest::build(self, "getAlbumList2")
+            .param("size", &size.to_string())
+            .param("offset", &offset.to_string())
+            .optional_param("musicFolderId", music_folder_id.map(String::as_str));
+        for (param, value) in list_type.params() {
+            req = req.param(param, &value);
+        }
+        req.call()
+    }
+
+    /// A page of the songs of the genre, starting at `offset`. At most 500 songs are returned.
+    pub fn get_songs_by_genre(
+        &self,
+        genre: &str,
+        count: usize,
+        offset: usize,
+        music_folder_id: Option<&Id>,
+    ) -> Result<SubsonicResponse> {
+        let req = ApiRequest::build(self, "getSongsByGenre")
+            .param("genre", genre)
+            .param("count", &count.to_string())
+            .param("offset", &offset.to_string())
+            .optional_param("musicFolderId", music_folder_id.map(String::as_str));
+        req.call()
+    }
+
+    pub fn get_random_songs(
+        &self,
+        size: usize,
+        music_folder_id: Option<&Id>,
+    ) -> Result<SubsonicResponse> {
+        let req = ApiRequest::build(self, "getRandomSongs")
+            .param("size", &size.to_string())
+            .optional_param("musicFolderId", music_folder_id.map(String::as_str));
+        req.call()
+    }
+
+    pub fn get_song(&self, id: &Id) -> Result<SubsonicResponse> {
+        let req = ApiRequest::build(self, "getSong").param("id", id);
+        req.call()
+    }
+
+    pub fn get_artist_info2(&self, id: &Id, count: usize) -> Result<SubsonicResponse> {
+        let req = ApiRequest::build(self, "getArtistInfo2")
+            .param("id", id)
+            .param("count", &count.to_string());
+        req.call()
+    }
+
+    /// The top songs of the artist by name, as known to Last.fm.
+    pub fn get_top_songs(&self, artist: &str, count: usize) -> Result<SubsonicResponse> {
+        let req = ApiRequest::build(self, "getTopSongs")
+            .param("artist", artist)
+            .param("count", &count.to_string());
+        req.call()
+    }
+
+    /// Lyrics by artist and title, as plain text.
+    pub fn get_lyrics(&self, artist: &str, title: &str) -> Result<SubsonicResponse> {
+        let req = ApiRequest::build(self, "getLyrics")
+            .param("artist", artist)
+            .param("title", title);
+        req.call()
+    }
+
+    /// Lyrics of a song, synced if available. Requires the `songLyrics` extension.
+    pub fn get_lyrics_by_song_id(&self, id: &Id) -> Result<SubsonicResponse> {
+        let req = ApiRequest::build(self, "getLyricsBySongId").param("id", id);
+        req.call()
+    }
+
+    pub fn search3(
+        &self,
+        query: &str,
+        artist_count: usize,
+        album_count: usize,
+        song_count: usize,
+        music_folder_id: Option<&Id>,
+    ) -> Result<SubsonicResponse> {
+        let req = ApiRequest::build(self, "search3")
+            .param("query", query)
+            .param("artistCount", &artist_count.to_string())
+            .param("albumCount", &album_count.to_string())
+            .param("songCount", &song_count.to_string())
+            .optional_param("musicFolderId", music_folder_id.map(String::as_str));
+        req.call()
+    }
+
+    pub fn get_starred2(&self, music_folder_id: Option<&Id>) -> Result<SubsonicResponse> {
+        let req = ApiRequest::build(self, "getStarred2")
+            .optional_param("musicFolderId", music_folder_id.map(String::as_str));
+        req.call()
+    }
+
+    pub fn star(&self, target: StarTarget) -> Result<SubsonicResponse> {
+        ApiRequest::build(self, "star").star_target(target).call()
+    }
+
+    pub fn unstar(&self, target: StarTarget) -> Result<SubsonicResponse> {
+        ApiRequest::build(self, "unstar").star_target(target).call()
+    }
+
+    /// Rate an artist, album or song from 1 to 5 stars, or remove the rating with 0.
+    pub fn set_rating(&self, id: &Id, rating: u8) -> Result<SubsonicResponse> {
+        let req = ApiRequest::build(self, "setRating")
+            .param("id", id)
+            .param("rating", &rating.to_string());
+        req.call()
+    }
+
+    pub fn get_playlists(&self) -> Result<SubsonicResponse> {
+        ApiRequest::build(self, "getPlaylists").call()
+    }
+
+    pub fn get_playlist(&self, id: &Id) -> Result<SubsonicResponse> {
+        let req = ApiRequest::build(self, "getPlaylist").param("id", id);
+        req.call()
+    }
+
+    pub fn create_playlist(&self, name: &str, song_ids: &[Id]) -> Result<SubsonicResponse> {
+        let req = ApiRequest::build(self, "createPlaylist").param("name", name);
+        song_ids
+            .iter()
+            .fold(req, |req, song_id| req.param("songId", song_id))
+            .call()
+    }
+
+    /// Replace all songs of a playlist, e.g. to reorder them.
+    pub fn set_playlist_songs(&self, id: &Id, song_ids: &[Id]) -> Result<SubsonicResponse> {
+        let req = ApiRequest::build(self, "createPlaylist").param("playlistId", id);
+        song_ids
+            .iter()
+            .fold(req, |req, song_id| req.param("songId", song_id))
+            .call()
+    }
+
+    /// Rename a playlist, add songs to its end and remove songs by their position. The
+    /// positions refer to the playlist before the songs are added.
+    pub fn update_playlist(
+        &self,
+        id: &Id,
+        name: Option<&str>,
+        song_ids_to_add: &[Id],
+        song_indexes_to_remove: &[usize],
+    ) -> Result<SubsonicResponse> {
+        let req = ApiRequest::build(self, "updatePlaylist")
+            .param("playlistId", id)
+            .optional_param("name", name);
+        let req = song_ids_to_add
+            .iter()
+            .fold(req, |req, song_id| req.param("songIdToAdd", song_id));
+        song_indexes_to_remove
+            .iter()
+            .fold(req, |req, index| {
+                req.param("songIndexToRemove", &index.to_string())
+            })
+            .call()
+    }
+
+    pub fn delete_playlist(&self, id: &Id) -> Result<SubsonicResponse> {
+        let req = ApiRequest::build(self, "deletePlaylist").param("id", id);
+        req.call()
+    }
+
+    pub fn get_play_queue(&self) -> Result<SubsonicResponse> {
+        ApiRequest::build(self, "getPlayQueue").call()
+    }
+
+    /// Store the play queue on the server, with the current song and the position in it.
+    pub fn save_play_queue(
+        &self,
+        song_ids: &[Id],
+        current: Option<&Id>,
+        position: Duration,
+    ) -> Result<SubsonicResponse> {
+        let req = ApiRequest::build(self, "savePlayQueue");
+        let req = song_ids
+            .iter()
+            .fold(req, |req, song_id| req.param("id", song_id));
+        let req = match current {
+            Some(current) => req
+                .param("current", current)
+                .param("position", &position.as_millis().to_string()),
+            None => req,
+        };
+        req.call()
+    }
+
+    /// Report a song as played at `time`, or as playing now if `submission` is false.
+    pub fn scrobble(
+        &self,
+        id: &Id,
+        time: Option<DateTime<Utc>>,
+        submission: bool,
+    ) -> Result<SubsonicResponse> {
+        let time = time.map(|time| time.timestamp_millis().to_string());
+        let req = ApiRequest::build(self, "scrobble")
+            .param("id", id)
+            .optional_param("time", time.as_deref())
+            .param("submission", &submission.to_string());
+        req.call()
+    }
+
+    /// Fetch cover art, scaled by the server to `size` pixels if given.
+    pub fn get_cover_art(&self, id: &Id, size: Option<u32>) -> Result<Box<dyn Read + Send + Sync>> {
+        let size = size.map(|size| size.to_string());
+        let req = ApiRequest::build(self, "getCoverArt")
+            .param("id", id)
+            .optional_param("size", size.as_deref());
+        req.call_stream()
+    }
+
+    /// The audio data of a song, starting at `offset` as returned by `stream_offset`.
+    pub fn stream(&self, id: &Id, offset: Duration) -> Result<Box<dyn Read + Send + Sync>> {
+        self.stream_request(id, offset).call_stream()
+    }
+
+    fn stream_request(&self, id: &Id, offset: Duration) -> ApiRequest {
+        let offset = (!offset.is_zero()).then(|| offset.as_secs().to_string());
+        ApiRequest::build(self, "stream")
+            .param("id", id)
+            .optional_param("timeOffset", offset.as_deref())
+    }
+
+    /// Where a stream can start to reach the position: at the whole second before it with the
+    /// `transcodeOffset` extension, else at the start of the song.
+    pub fn stream_offset(&self, position: Duration) -> Duration {
+        if self.supports(Extension::TranscodeOffset) {
+            Duration::from_secs(position.as_secs())
+        } else {
+            Duration::ZERO
+        }
+    }
 }
 
 struct ApiRequest {
-    request: Request,
+    url: String,
+    params: Vec<(String, String)>,
+    format: ResponseFormat,
+    form_post: bool,
 }
 
 impl ApiRequest {
@@ -64,40 +558,120 @@ impl ApiRequest {
         let url = format!("{}/rest/{}", connection.url, endpoint);
 
         let salt = format!("{:x}", rand::random::<u64>());
-        let token = format!(
-            "{:032x}",
-            md5::Md5::digest(format!("{}{}", connection.password.expose_secret(), salt))
-        );
-
-        // let salt_test = "c19b2d";
-        // let token_test = format!("{:032x}", md5::Md5::digest(format!("sesame{}", salt_test)));
-        // assert_eq!(token_test, "26719a1196d2a940705a59634eb18eab");
+        let mut request = ApiRequest {
+            url,
+            params: vec![],
+            format: connection.format,
+            form_post: connection.supports(Extension::FormPost),
+        }
+        .param("v", SUBSONIC_API_VERSION)
+        .param("c", SUBSONIC_CLIENT_NAME);
+        for (param, value) in auth_params(
+            connection.auth_mode,
+            &connection.user,
+            connection.password.expose_secret(),
+            &salt,
+        ) {
+            request = request.param(param, &value);
+        }
+        if connection.format == ResponseFormat::Json {
+            request = request.param("f", "json");
+        }
+        request
+    }
 
-        let request = ureq::get(&url)
-            .query("v", SUBSONIC_API_VERSION)
-            .query("c", SUBSONIC_CLIENT_NAME)
-            .query("u", &connection.user)
-            .query("s", &salt)
-            .query("t", &token);
+    fn param(mut self, param: &str, value: &str) -> Self {
+        self.params.push((param.to_string(), value.to_string()));
+        self
+    }
 
-        ApiRequest { request }
+    fn star_target(self, target: StarTarget) -> Self {
+        match target {
+            StarTarget::Artist(id) => self.param("artistId", id),
+            StarTarget::Album(id) => self.param("albumId", id),
+            StarTarget::Song(id) => self.param("id", id),
+        }
     }
 
-    fn param(self, param: &str, value: &str) -> Self {
-        ApiRequest {
-            request: self.request.query(param, value),
+    fn optional_param(self, param: &str, value: Option<&str>) -> Self {
+        match value {
+            Some(value) => self.param(param, value),
+            None => self,
         }
     }
 
-    fn call(self) -> Result<SubsonicResponse> {
-        let resp = self.request.call()?;
-        let body = resp.into_string()?;
-        let parsed_resp: SubsonicResponse = serde_xml_rs::from_str(&body)?;
-        if let Some(SubsonicData::Error(err)) = &parsed_resp.data {
-            Err(ConnectionError::from(err).into())
+    fn send(&self) -> Result<ureq::Response> {
+        let params: Vec<_> = self
+            .params
+            .iter()
+            .map(|(param, value)| (param.as_str(), value.as_str()))
+            .collect();
+        let resp = if self.form_post {
+            // keeps the credentials out of the URL, which may end up in server logs
+            agent().post(&self.url).send_form(&params)?
         } else {
-            Ok(parsed_resp)
+            params
+                .iter()
+                .fold(agent().get(&self.url), |request, (param, value)| {
+                    request.query(param, value)
+                })
+                .call()?
+        };
+        Ok(resp)
+    }
+
+    fn call(self) -> Result<SubsonicResponse> {
+        let body = self.send()?.into_string()?;
+        parse_response(&body, self.format)
+    }
+
+    fn call_stream(self) -> Result<Box<dyn Read + Send + Sync>> {
+        let resp = self.send()?;
+        // on failure, the server answers with a regular response instead of the media data
+        let format = match resp.content_type() {
+            content_type if content_type.ends_with("json") => Some(ResponseFormat::Json),
+            content_type if content_type.ends_with("xml") => Some(ResponseFormat::Xml),
+            _ => None,
+        };
+        if let Some(format) = format {
+            let body = resp.into_string()?;
+            parse_response(&body, format)?;
+            bail!("unexpected response to stream request");
         }
+        Ok(resp.into_reader())
+    }
+}
+
+/// Query parameters authenticating a request. `salt` is only used for token authentication.
+fn auth_params(
+    auth_mode: AuthMode,
+    user: &str,
+    password: &str,
+    salt: &str,
+) -> Vec<(&'static str, String)> {
+    match auth_mode {
+        AuthMode::Token => {
+            let token = format!("{:032x}", md5::Md5::digest(format!("{}{}", password, salt)));
+            vec![("u", user.into()), ("s", salt.into()), ("t", token)]
+        }
+        AuthMode::Password => {
+            let hex: String = password.bytes().map(|b| format!("{:02x}", b)).collect();
+            vec![("u", user.into()), ("p", format!("enc:{}", hex))]
+        }
+        // the key identifies the user, sending `u` as well is an error
+        AuthMode::ApiKey => vec![("apiKey", password.into())],
+    }
+}
+
+fn parse_response(body: &str, format: ResponseFormat) -> Result<SubsonicResponse> {
+    let parsed_resp: SubsonicResponse = match format {
+        ResponseFormat::Json => serde_json::from_str::<JsonEnvelope>(body)?.response.into(),
+        ResponseFormat::Xml => serde_xml_rs::from_str(body)?,
+    };
+    if let Some(SubsonicData::Error(err)) = &parsed_resp.data {
+        Err(ConnectionError::from(err).into())
+    } else {
+        Ok(parsed_resp)
     }
 }
 
@@ -128,6 +702,7 @@ pub enum ConnectionErrorType {
     WrongCredentials,
     NotAuthorized,
     NotFound,
+    #[allow(dead_code)]
     Other(usize),
 }
 
@@ -148,3 +723,451 @@ impl ConnectionError {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use secrecy::Secret;
+
+    use super::{
+        auth_params, parse_response, AlbumListType, AuthMode, Connection, ConnectionError,
+        Extension, ResponseFormat,
+    };
+    use crate::subsonic::{SubsonicData, SubsonicResponse};
+
+    /// The XML and JSON variant of a response fixture.
+    macro_rules! fixture {
+        ($name:literal) => {
+            [
+                (
+                    ResponseFormat::Xml,
+                    include_str!(concat!("../tests/fixtures/", $name, ".xml")),
+                ),
+                (
+                    ResponseFormat::Json,
+                    include_str!(concat!("../tests/fixtures/", $name, ".json")),
+                ),
+            ]
+        };
+    }
+
+    fn parse(fixture: [(ResponseFormat, &str); 2]) -> Vec<SubsonicResponse> {
+        fixture
+            .into_iter()
+            .map(|(format, body)| parse_response(body, format).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn parse_ping() {
+        for resp in parse(fixture!("ping")) {
+            assert_eq!(resp.status, "ok");
+            assert_eq!(resp.version, "1.16.1");
+            assert!(resp.data.is_none());
+        }
+    }
+
+    #[test]
+    fn parse_error() {
+        for (format, body) in fixture!("error") {
+            let err = parse_response(body, format).err().unwrap();
+            let err = err.downcast_ref::<ConnectionError>().unwrap();
+            assert_eq!(
+                err.to_string(),
+                "WrongCredentials : Wrong username or password"
+            );
+        }
+    }
+
+    #[test]
+    fn parse_open_subsonic_extensions() {
+        for resp in parse(fixture!("open_subsonic_extensions")) {
+            assert!(resp.open_subsonic);
+            assert_eq!(resp.server_type.as_deref(), Some("navidrome"));
+            assert_eq!(resp.server_version.as_deref(), Some("0.53.3"));
+            let Some(SubsonicData::OpenSubsonicExtensions(extensions)) = resp.data else {
+                panic!("not an extensions response");
+            };
+            let names: Vec<_> = extensions.iter().map(|e| e.name.as_str()).collect();
+            assert_eq!(names, ["transcodeOffset", "formPost", "songLyrics"]);
+            assert_eq!(extensions[0].versions, [1]);
+        }
+    }
+
+    #[test]
+    fn parse_artists() {
+        for resp in parse(fixture!("artists")) {
+            let Some(SubsonicData::Artists(artists)) = resp.data else {
+                panic!("not an artists response");
+            };
+            let names: Vec<Vec<&str>> = artists
+                .index
+                .iter()
+                .map(|index| index.artist.iter().map(|a| a.name.as_str()).collect())
+                .collect();
+            assert_eq!(names, [vec!["The Beatles", "Björk"], vec!["Queen"]]);
+            assert_eq!(artists.index[1].name, "Q");
+            assert_eq!(artists.index[0].artist[0].album_count, 2);
+        }
+    }
+
+    #[test]
+    fn parse_album() {
+        for resp in parse(fixture!("album")) {
+            let Some(SubsonicData::Album(album)) = resp.data else {
+                panic!("not an album response");
+            };
+            assert_eq!(album.name, "Beatles for Sale");
+            assert_eq!(album.artist_id.as_deref(), Some("ar1"));
+            assert_eq!(album.created.to_rfc3339(), "2023-11-14T22:13:20+00:00");
+            assert_eq!(album.year, Some(1964));
+            let songs: Vec<_> = album
+                .song
+                .iter()
+                .map(|song| (song.id.as_str(), song.track, song.duration, song.is_dir))
+                .collect();
+            assert_eq!(
+                songs,
+                [
+                    ("s1", Some(8), Some(164), false),
+                    ("s2", Some(4), Some(151), false)
+                ]
+            );
+        }
+    }
+
+    #[test]
+    fn parse_music_folders() {
+        for resp in parse(fixture!("music_folders")) {
+            let Some(SubsonicData::MusicFolders(folders)) = resp.data else {
+                panic!("not a music folders response");
+            };
+            let folders: Vec<_> = folders
+                .music_folder
+                .iter()
+                .map(|folder| (folder.id.as_str(), folder.name.as_deref()))
+                .collect();
+            assert_eq!(folders, [("1", Some("Music")), ("2", Some("Audiobooks"))]);
+        }
+    }
+
+    #[test]
+    fn parse_indexes() {
+        for resp in parse(fixture!("indexes")) {
+            let Some(SubsonicData::Indexes(indexes)) = resp.data else {
+                panic!("not an indexes response");
+            };
+            assert_eq!(indexes.last_modified, 1700000000000);
+            let names: Vec<Vec<&str>> = indexes
+                .index
+                .iter()
+                .map(|index| index.artist.iter().map(|a| a.name.as_str()).collect())
+                .collect();
+            assert_eq!(names, [vec!["The Beatles", "Björk"], vec!["Queen"]]);
+            assert_eq!(indexes.index[0].artist[0].id, "d1");
+            assert_eq!(indexes.child.len(), 1);
+            assert_eq!(indexes.child[0].title, "Untitled");
+        }
+    }
+
+    #[test]
+    fn parse_genres() {
+        for resp in parse(fixture!("genres")) {
+            let Some(SubsonicData::Genres(genres)) = resp.data else {
+                panic!("not a genres response");
+            };
+            let genres: Vec<_> = genres
+                .genre
+                .iter()
+                .map(|genre| (genre.name.as_str(), genre.song_count, genre.album_count))
+                .collect();
+            assert_eq!(genres, [("Rock", 28, 4), ("Trip-Hop", 3, 1)]);
+        }
+    }
+
+    #[test]
+    fn parse_album_list2() {
+        for resp in parse(fixture!("album_list2")) {
+            let Some(SubsonicData::AlbumList2(list)) = resp.data else {
+                panic!("not an album list response");
+            };
+            let albums: Vec<_> = list
+                .album
+                .iter()
+                .map(|album| (album.id.as_str(), album.artist_id.as_deref(), album.year))
+                .collect();
+            assert_eq!(
+                albums,
+                [
+                    ("al3", Some("ar3"), Some(1975)),
+                    ("al1", Some("ar1"), Some(1964))
+                ]
+            );
+        }
+    }
+
+    #[test]
+    fn album_list_params() {
+        assert_eq!(
+            AlbumListType::ByYear(1990, 1999).params(),
+            [
+                ("type", "byYear".into()),
+                ("fromYear", "1990".into()),
+                ("toYear", "1999".into())
+            ]
+        );
+        assert_eq!(AlbumListType::Newest.params(), [("type", "newest".into())]);
+    }
+
+    #[test]
+    fn stream_offset() {
+        let mut conn = Connection::new(
+            "https://example.com".into(),
+            "alice".into(),
+            Secret::new("sesame".into()),
+            AuthMode::Token,
+            ResponseFormat::Json,
+        );
+        let position = Duration::from_millis(61500);
+        let time_offset = |conn: &Connection, offset| {
+            conn.stream_request(&"s1".into(), offset)
+                .params
+                .into_iter()
+                .find(|(param, _)| param == "timeOffset")
+                .map(|(_, value)| value)
+        };
+
+        // without the extension, the stream starts at the beginning and the player seeks in it
+        let offset = conn.stream_offset(position);
+        assert_eq!(offset, Duration::ZERO);
+        assert_eq!(time_offset(&conn, offset), None);
+
+        conn.extensions
+            .insert(Extension::TranscodeOffset.name().to_string());
+        let offset = conn.stream_offset(position);
+        assert_eq!(offset, Duration::from_secs(61));
+        assert_eq!(time_offset(&conn, offset).as_deref(), Some("61"));
+    }
+
+    #[test]
+    fn parse_starred2() {
+        for resp in parse(fixture!("starred2")) {
+            let Some(SubsonicData::Starred2(starred)) = resp.data else {
+                panic!("not a starred response");
+            };
+            assert_eq!(starred.artist[0].name, "Queen");
+            assert!(starred.artist[0].starred.is_some());
+            assert_eq!(starred.album[0].user_rating, Some(5));
+            let songs: Vec<_> = starred
+                .song
+                .iter()
+                .map(|song| (song.id.as_str(), song.user_rating))
+                .collect();
+            assert_eq!(songs, [("s1", Some(4))]);
+        }
+    }
+
+    #[test]
+    fn parse_playlists() {
+        for resp in parse(fixture!("playlists")) {
+            let Some(SubsonicData::Playlists(playlists)) = resp.data else {
+                panic!("not a playlists response");
+            };
+            let names: Vec<_> = playlists
+                .playlist
+                .iter()
+                .map(|playlist| (playlist.name.as_str(), playlist.song_count))
+                .collect();
+            assert_eq!(names, [("Road trip", 2), ("Focus", 0)]);
+            assert_eq!(playlists.playlist[0].owner.as_deref(), Some("alice"));
+            assert_eq!(playlists.playlist[1].public, Some(false));
+            assert!(playlists.playlist[0].entry.is_empty());
+        }
+    }
+
+    #[test]
+    fn parse_playlist() {
+        for resp in parse(fixture!("playlist")) {
+            let Some(SubsonicData::Playlist(playlist)) = resp.data else {
+                panic!("not a playlist response");
+            };
+            assert_eq!(playlist.id, "pl1");
+            assert_eq!(playlist.comment.as_deref(), Some("for the summer"));
+            let ids: Vec<_> = playlist.entry.iter().map(|song| song.id.as_str()).collect();
+            assert_eq!(ids, ["s3", "s1"]);
+        }
+    }
+
+    #[test]
+    fn parse_play_queue() {
+        for resp in parse(fixture!("play_queue")) {
+            let Some(SubsonicData::PlayQueue(play_queue)) = resp.data else {
+                panic!("not a play queue response");
+            };
+            assert_eq!(play_queue.current.as_deref(), Some("s1"));
+            assert_eq!(play_queue.position, Some(61500));
+            assert_eq!(play_queue.changed_by, "Substreamer");
+            assert_eq!(play_queue.changed.to_rfc3339(), "2024-03-02T08:30:00+00:00");
+            let ids: Vec<_> = play_queue
+                .entry
+                .iter()
+                .map(|song| song.id.as_str())
+                .collect();
+            assert_eq!(ids, ["s3", "s1"]);
+        }
+    }
+
+    #[test]
+    fn parse_song() {
+        for resp in parse(fixture!("song")) {
+            let Some(SubsonicData::Song(song)) = resp.data else {
+                panic!("not a song response");
+            };
+            assert_eq!(song.title, "Eight Days a Week");
+            assert_eq!(song.album_id.as_deref(), Some("al1"));
+            assert_eq!(song.duration, Some(164));
+            assert_eq!(song.cover_art.as_deref(), Some("al-al1"));
+        }
+    }
+
+    #[test]
+    fn parse_artist_info2() {
+        for resp in parse(fixture!("artist_info2")) {
+            let Some(SubsonicData::ArtistInfo2(info)) = resp.data else {
+                panic!("not an artist info response");
+            };
+            assert!(info.biography.unwrap().starts_with("The Beatles were"));
+            assert_eq!(
+                info.music_brainz_id.as_deref(),
+                Some("b10bbbfc-cf9e-42e0-be17-e2c3e1d2600d")
+            );
+            assert_eq!(
+                info.large_image_url.as_deref(),
+                Some("https://example.com/beatles-large.jpg")
+            );
+            let similar: Vec<_> = info
+                .similar_artist
+                .iter()
+                .map(|artist| (artist.id.as_str(), artist.name.as_str()))
+                .collect();
+            assert_eq!(similar, [("ar3", "Queen")]);
+        }
+    }
+
+    #[test]
+    fn parse_random_songs() {
+        for resp in parse(fixture!("random_songs")) {
+            let Some(SubsonicData::RandomSongs(songs)) = resp.data else {
+                panic!("not a random songs response");
+            };
+            let ids: Vec<_> = songs.song.iter().map(|song| song.id.as_str()).collect();
+            assert_eq!(ids, ["s3", "s1"]);
+        }
+    }
+
+    #[test]
+    fn parse_songs_by_genre() {
+        for resp in parse(fixture!("songs_by_genre")) {
+            let Some(SubsonicData::SongsByGenre(songs)) = resp.data else {
+                panic!("not a songs by genre response");
+            };
+            let genres: Vec<_> = songs
+                .song
+                .iter()
+                .map(|song| song.genre.as_deref())
+                .collect();
+            assert_eq!(genres, [Some("Rock"), Some("Rock")]);
+        }
+    }
+
+    #[test]
+    fn parse_top_songs() {
+        for resp in parse(fixture!("top_songs")) {
+            let Some(SubsonicData::TopSongs(songs)) = resp.data else {
+                panic!("not a top songs response");
+            };
+            let titles: Vec<_> = songs.song.iter().map(|song| song.title.as_str()).collect();
+            assert_eq!(titles, ["Bohemian Rhapsody"]);
+        }
+    }
+
+    #[test]
+    fn parse_lyrics() {
+        for resp in parse(fixture!("lyrics")) {
+            let Some(SubsonicData::Lyrics(lyrics)) = resp.data else {
+                panic!("not a lyrics response");
+            };
+            assert_eq!(lyrics.artist.as_deref(), Some("The Beatles"));
+            assert_eq!(lyrics.title.as_deref(), Some("Eight Days a Week"));
+            assert!(lyrics.value.starts_with("Ooh, I need your love, babe"));
+            assert_eq!(lyrics.value.lines().count(), 2);
+        }
+    }
+
+    #[test]
+    fn parse_lyrics_list() {
+        for resp in parse(fixture!("lyrics_list")) {
+            let Some(SubsonicData::LyricsList(list)) = resp.data else {
+                panic!("not a lyrics list response");
+            };
+            let [synced, plain] = &list.structured_lyrics[..] else {
+                panic!("expected two lyrics");
+            };
+            assert!(synced.synced);
+            assert_eq!(synced.lang, "eng");
+            assert_eq!(synced.offset, Some(-100));
+            let lines: Vec<_> = synced
+                .line
+                .iter()
+                .map(|line| (line.start, line.value.as_str()))
+                .collect();
+            assert_eq!(
+                lines,
+                [
+                    (Some(0), "Ooh, I need your love, babe"),
+                    (Some(2500), "Guess you know it's true")
+                ]
+            );
+            assert!(!plain.synced);
+            assert_eq!(plain.line[0].start, None);
+        }
+    }
+
+    fn params(auth_mode: AuthMode) -> Vec<(&'static str, String)> {
+        auth_params(auth_mode, "admin", "sesame", "c19b2d")
+    }
+
+    #[test]
+    fn token_auth() {
+        // example from the Subsonic API documentation
+        assert_eq!(
+            params(AuthMode::Token),
+            [
+                ("u", "admin".into()),
+                ("s", "c19b2d".into()),
+                ("t", "26719a1196d2a940705a59634eb18eab".into())
+            ]
+        );
+    }
+
+    #[test]
+    fn password_auth() {
+        assert_eq!(
+            params(AuthMode::Password),
+            [("u", "admin".into()), ("p", "enc:736573616d65".into())]
+        );
+    }
+
+    #[test]
+    fn api_key_auth() {
+        assert_eq!(params(AuthMode::ApiKey), [("apiKey", "sesame".into())]);
+    }
+
+    #[test]
+    fn parse_auth_mode() {
+        assert_eq!("token".parse::<AuthMode>().unwrap(), AuthMode::Token);
+        assert_eq!("api_key".parse::<AuthMode>().unwrap(), AuthMode::ApiKey);
+        assert!("md5".parse::<AuthMode>().is_err());
+    }
+}
@@ -0,0 +1,344 @@
+use std::{fmt::Write, io::Cursor};
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use color_eyre::{eyre::bail, Result};
+use image::{imageops::FilterType, ImageFormat, RgbImage};
+use tui::{
+    style::{Color, Style},
+    text::{Span, Spans},
+};
+
+/// Size in pixels of the cover art requested from the server.
+pub const COVER_SIZE: u32 = 300;
+/// Assumed size of a terminal cell in pixels, for the protocols which draw in pixels.
+pub const CELL_WIDTH: u32 = 8;
+pub const CELL_HEIGHT: u32 = 16;
+/// Deletes all images shown with the Kitty graphics protocol.
+pub const KITTY_CLEAR: &str = "\x1b_Ga=d,q=2\x1b\\";
+
+/// Maximum size of a chunk of image data in a Kitty graphics command.
+const KITTY_CHUNK: usize = 4096;
+
+/// How cover art is drawn in the terminal.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Graphics {
+    Kitty,
+    Sixel,
+    /// Unicode half blocks, two pixels per cell, works in any terminal with true color
+    Blocks,
+    None,
+}
+
+impl Graphics {
+    /// The protocol selected by the `graphics` setting, detected from the environment if unset.
+    pub fn select(setting: Option<&str>) -> Result<Graphics> {
+        match setting {
+            Some("kitty") => Ok(Graphics::Kitty),
+            Some("sixel") => Ok(Graphics::Sixel),
+            Some("blocks") => Ok(Graphics::Blocks),
+            Some("none") => Ok(Graphics::None),
+            None | Some("auto") => Ok(detect(|name| std::env::var(name).ok())),
+            Some(setting) => bail!("unknown graphics: {}", setting),
+        }
+    }
+}
+
+/// Guess the graphics protocol supported by the terminal from its environment variables.
+/// Only `TERM` is passed on over SSH.
+fn detect(var: impl Fn(&str) -> Option<String>) -> Graphics {
+    let term = var("TERM").unwrap_or_default();
+    let term_program = var("TERM_PROGRAM").unwrap_or_default();
+    if var("KITTY_WINDOW_ID").is_some()
+        || term.contains("kitty")
+        || term.contains("ghostty")
+        || matches!(term_program.as_str(), "WezTerm" | "ghostty")
+    {
+        Graphics::Kitty
+    } else if term.contains("sixel")
+        || ["foot", "mlterm", "contour"]
+            .iter()
+            .any(|name| term.starts_with(name))
+    {
+        Graphics::Sixel
+    } else {
+        Graphics::Blocks
+    }
+}
+
+/// A decoded cover image.
+pub struct Cover {
+    image: RgbImage,
+}
+
+impl Cover {
+    /// Decode a PNG or JPEG image.
+    pub fn decode(data: &[u8]) -> Result<Cover> {
+        Ok(Cover {
+            image: image::load_from_memory(data)?.to_rgb8(),
+        })
+    }
+
+    /// The image scaled to fit into `width` x `height` pixels, keeping its aspect ratio.
+    pub fn fit(&self, width: u32, height: u32) -> RgbImage {
+        let (width, height) = fit_size(self.image.width(), self.image.height(), width, height);
+        image::imageops::resize(&self.image, width, height, FilterType::Triangle)
+    }
+}
+
+/// The largest size with the aspect ratio of `width` x `height` fitting into the bounds.
+fn fit_size(width: u32, height: u32, max_width: u32, max_height: u32) -> (u32, u32) {
+    if width == 0 || height == 0 || max_width == 0 || max_height == 0 {
+        return (0, 0);
+    }
+    let (width, height) = (width as u64, height as u64);
+    let (max_width, max_height) = (max_width as u64, max_height as u64);
+    if width * max_height <= height * max_width {
+        (
+            (width * max_height / height).max(1) as u32,
+            max_height as u32,
+        )
+    } else {
+        (max_width as u32, (height * max_width / width).max(1) as u32)
+    }
+}
+
+/// Lines of upper half blocks, the foreground being the upper and the background the lower
+/// pixel of a cell.
+pub fn half_blocks(image: &RgbImage) -> Vec<Spans<'static>> {
+    let rgb = |x, y| {
+        let [r, g, b] = image.get_pixel(x, y).0;
+        Color::Rgb(r, g, b)
+    };
+    (0..image.height())
+        .step_by(2)
+        .map(|y| {
+            let spans: Vec<_> = (0..image.width())
+                .map(|x| {
+                    let lower = if y + 1 < image.height() {
+                        rgb(x, y + 1)
+                    } else {
+                        Color::Reset
+                    };
+                    Span::styled("▀", Style::default().fg(rgb(x, y)).bg(lower))
+                })
+                .collect();
+            Spans::from(spans)
+        })
+        .collect()
+}
+
+/// Kitty graphics protocol commands showing the image at the cursor, scaled to `cols` x `rows`
+/// cells by the terminal. Responses of the terminal are suppressed, as they would end up in
+/// the keyboard input.
+pub fn kitty(image: &RgbImage, cols: u16, rows: u16) -> Result<String> {
+    let mut png = vec![];
+    image.write_to(&mut Cursor::new(&mut png), ImageFormat::Png)?;
+    let data = STANDARD.encode(png);
+
+    let mut commands = String::new();
+    let chunks: Vec<_> = data.as_bytes().chunks(KITTY_CHUNK).collect();
+    for (i, chunk) in chunks.iter().enumerate() {
+        let more = (i + 1 < chunks.len()) as u8;
+        let chunk = std::str::from_utf8(chunk)?;
+        if i == 0 {
+            write!(
+                commands,
+                "\x1b_Ga=T,f=100,q=2,c={},r={},m={};{}\x1b\\",
+                cols, rows, more, chunk
+            )?;
+        } else {
+            write!(commands, "\x1b_Gm={};{}\x1b\\", more, chunk)?;
+        }
+    }
+    Ok(commands)
+}
+
+/// Sixel data of the image, with its colors reduced to a 6x6x6 color cube.
+pub fn sixel(image: &RgbImage) -> String {
+    let level = |c: u8| (c as u32 * 5 + 127) / 255;
+    let index = |x, y| {
+        let [r, g, b] = image.get_pixel(x, y).0;
+        (level(r) * 36 + level(g) * 6 + level(b)) as usize
+    };
+
+    let mut sixel = format!("\x1bPq\"1;1;{};{}", image.width(), image.height());
+    for i in 0..216 {
+        let percent = |level| level * 100 / 5;
+        let _ = write!(
+            sixel,
+            "#{};2;{};{};{}",
+            i,
+            percent(i / 36),
+            percent(i / 6 % 6),
+            percent(i % 6)
+        );
+    }
+    for top in (0..image.height()).step_by(6) {
+        let rows = top..(top + 6).min(image.height());
+        // the six pixel rows of a band as color indices, column by column
+        let band: Vec<Vec<usize>> = (0..image.width())
+            .map(|x| rows.clone().map(|y| index(x, y)).collect())
+            .collect();
+        let mut colors: Vec<_> = band.iter().flatten().copied().collect();
+        colors.sort_unstable();
+        colors.dedup();
+
+        for color in colors {
+            let _ = write!(sixel, "#{}", color);
+            let bits = band.iter().map(|column| {
+                column
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, &c)| c == color)
+                    .fold(0, |bits, (dy, _)| bits | 1 << dy)
+            });
+            push_runs(&mut sixel, bits);
+            // back to the start of the band for the next color
+            sixel.push('$');
+        }
+        sixel.push('-');
+    }
+    sixel.push_str("\x1b\\");
+    sixel
+}
+
+/// Append sixel characters, with runs of the same character length encoded.
+fn push_runs(sixel: &mut String, bits: impl Iterator<Item = u8>) {
+    let mut run: Option<(u8, usize)> = None;
+    let flush = |sixel: &mut String, (bits, count): (u8, usize)| {
+        let c = (63 + bits) as char;
+        if count > 3 {
+            let _ = write!(sixel, "!{}{}", count, c);
+        } else {
+            sixel.extend(std::iter::repeat_n(c, count));
+        }
+    };
+    for bits in bits {
+        run = match run {
+            Some((run_bits, count)) if run_bits == bits => Some((bits, count + 1)),
+            Some(previous) => {
+                flush(sixel, previous);
+                Some((bits, 1))
+            }
+            None => Some((bits, 1)),
+        };
+    }
+    if let Some(run) = run {
+        flush(sixel, run);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use base64::{engine::general_purpose::STANDARD, Engine};
+    use image::{Rgb, RgbImage};
+    use tui::style::Color;
+
+    use super::{detect, fit_size, half_blocks, kitty, sixel, Cover, Graphics};
+
+    const PNG: &[u8] = include_bytes!("../tests/fixtures/cover.png");
+    const JPEG: &[u8] = include_bytes!("../tests/fixtures/cover.jpg");
+
+    #[test]
+    fn decode() {
+        // left half red, right half blue
+        let png = Cover::decode(PNG).unwrap();
+        assert_eq!(png.image.dimensions(), (8, 4));
+        assert_eq!(*png.image.get_pixel(0, 0), Rgb([255, 0, 0]));
+        assert_eq!(*png.image.get_pixel(7, 3), Rgb([0, 0, 255]));
+
+        // top half green, bottom half white, with compression artifacts
+        let jpeg = Cover::decode(JPEG).unwrap();
+        assert_eq!(jpeg.image.dimensions(), (16, 16));
+        let [r, g, b] = jpeg.image.get_pixel(8, 2).0;
+        assert!(r < 32 && g > 224 && b < 32);
+        assert!(jpeg.image.get_pixel(8, 13).0.iter().all(|&c| c > 224));
+
+        assert!(Cover::decode(b"not an image").is_err());
+    }
+
+    #[test]
+    fn fit() {
+        assert_eq!(fit_size(8, 4, 4, 4), (4, 2));
+        assert_eq!(fit_size(300, 300, 40, 80), (40, 40));
+        assert_eq!(fit_size(300, 300, 80, 40), (40, 40));
+        assert_eq!(fit_size(1000, 1, 10, 10), (10, 1));
+        assert_eq!(fit_size(300, 300, 0, 10), (0, 0));
+
+        let png = Cover::decode(PNG).unwrap().fit(4, 4);
+        assert_eq!(png.dimensions(), (4, 2));
+        assert_eq!(*png.get_pixel(0, 0), Rgb([255, 0, 0]));
+        assert_eq!(*png.get_pixel(3, 1), Rgb([0, 0, 255]));
+    }
+
+    #[test]
+    fn blocks() {
+        let image = RgbImage::from_fn(2, 3, |_, y| Rgb([y as u8, 0, 0]));
+        let lines = half_blocks(&image);
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0].0.len(), 2);
+        assert_eq!(lines[0].0[0].content, "▀");
+        assert_eq!(lines[0].0[0].style.fg, Some(Color::Rgb(0, 0, 0)));
+        assert_eq!(lines[0].0[0].style.bg, Some(Color::Rgb(1, 0, 0)));
+        // the last row has no lower half
+        assert_eq!(lines[1].0[1].style.fg, Some(Color::Rgb(2, 0, 0)));
+        assert_eq!(lines[1].0[1].style.bg, Some(Color::Reset));
+    }
+
+    #[test]
+    fn kitty_chunks() {
+        // noise compresses badly, so that the image takes more than one chunk
+        let image = RgbImage::from_fn(64, 64, |x, y| {
+            let v = (x * 7919 + y * 104729) as u8;
+            Rgb([v, v.wrapping_mul(3), v.wrapping_mul(7)])
+        });
+        let commands = kitty(&image, 10, 5).unwrap();
+        assert!(commands.starts_with("\x1b_Ga=T,f=100,q=2,c=10,r=5,m=1;"));
+        assert!(commands.ends_with("\x1b\\"));
+
+        let data: String = commands
+            .split("\x1b\\")
+            .filter(|command| !command.is_empty())
+            .map(|command| command.split_once(';').unwrap().1)
+            .collect();
+        let png = STANDARD.decode(data).unwrap();
+        let decoded = Cover::decode(&png).unwrap();
+        assert_eq!(decoded.image, image);
+        assert!(commands.matches("m=0;").count() == 1);
+    }
+
+    #[test]
+    fn sixel_bands() {
+        let image = Cover::decode(PNG).unwrap().fit(8, 4);
+        let sixel = sixel(&image);
+        assert!(sixel.starts_with("\x1bPq\"1;1;8;4#0;2;0;0;0"));
+        assert!(sixel.ends_with("-\x1b\\"));
+        // one band of four rows: blue (index 5) on the right, red (index 180) on the left
+        assert!(sixel.contains("#5!4?!4N$"));
+        assert!(sixel.contains("#180!4N!4?$"));
+    }
+
+    #[test]
+    fn detect_terminal() {
+        let env = |vars: &'static [(&'static str, &'static str)]| {
+            move |name: &str| {
+                vars.iter()
+                    .find(|(var, _)| *var == name)
+                    .map(|(_, value)| value.to_string())
+            }
+        };
+        assert_eq!(detect(env(&[("TERM", "xterm-kitty")])), Graphics::Kitty);
+        assert_eq!(
+            detect(env(&[
+                ("TERM", "xterm-256color"),
+                ("TERM_PROGRAM", "WezTerm")
+            ])),
+            Graphics::Kitty
+        );
+        assert_eq!(detect(env(&[("TERM", "foot")])), Graphics::Sixel);
+        assert_eq!(detect(env(&[("TERM", "xterm-256color")])), Graphics::Blocks);
+        assert_eq!(detect(env(&[])), Graphics::Blocks);
+        assert_eq!(Graphics::select(Some("none")).unwrap(), Graphics::None);
+        assert!(Graphics::select(Some("ascii")).is_err());
+    }
+}
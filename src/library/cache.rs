@@ -1,40 +1,206 @@
-use std::collections::{HashMap, HashSet};
+use std::{
+    collections::{HashMap, HashSet},
+    fs::{self, File},
+    io::{BufReader, BufWriter, Write},
+    path::Path,
+};
 
-use crate::subsonic::{Id, SubsonicData, SubsonicResponse};
+use color_eyre::{eyre::bail, Result};
+use serde::{Deserialize, Serialize};
 
-use super::{Album, Artist, LibraryItem, LibraryItemKey, Song};
+use crate::{
+    conn::AlbumListType,
+    subsonic::{self, Id, SubsonicData, SubsonicResponse},
+};
 
+use super::{
+    request::FindType, Album, Artist, ArtistInfo, Genre, LibraryItem, LibraryItemKey, Marks,
+    Playlist, SearchString, Song, DECADES_TITLE, GENRES_TITLE, PLAYLISTS_TITLE, RANDOM_SONGS_TITLE,
+    STARRED_TITLE,
+};
+
+/// Version of the on-disk format, caches written with another version are discarded.
+const CACHE_VERSION: u32 = 4;
+
+#[derive(Serialize, Deserialize)]
 pub struct LibraryCache {
+    version: u32,
+    // `lastModified` of the server's artist index when it was last checked
+    last_modified: Option<u64>,
     indexes: HashMap<String, HashSet<LibraryItemKey>>,
     artists: HashMap<Id, CacheEntry<Artist>>,
     albums: HashMap<Id, CacheEntry<Album>>,
     songs: HashMap<Id, CacheEntry<Song>>,
+    // album lists change with every play, they are only kept for the session
+    #[serde(skip)]
+    album_lists: HashMap<AlbumListType, ListEntry>,
+    // the song counts of genres change with every scan, just like album lists
+    #[serde(skip)]
+    genres: Option<Vec<Genre>>,
+    #[serde(skip)]
+    genre_songs: HashMap<String, ListEntry>,
+    // starred items are also changed by other clients
+    #[serde(skip)]
+    starred: Option<Vec<LibraryItemKey>>,
+    // a new selection is drawn on refresh
+    #[serde(skip)]
+    random_songs: Option<Vec<Id>>,
+    // playlists are shared and edited by other users
+    #[serde(skip)]
+    playlists: Option<Vec<Id>>,
+    #[serde(skip)]
+    playlist_items: HashMap<Id, Playlist>,
+    // the songs in playlist order, a song may appear more than once
+    #[serde(skip)]
+    playlist_songs: HashMap<Id, Vec<Id>>,
+    // biographies and top songs come from Last.fm, they are only kept for the session
+    #[serde(skip)]
+    artist_infos: HashMap<Id, ArtistInfoEntry>,
 }
 
 impl LibraryCache {
     pub fn new() -> LibraryCache {
         LibraryCache {
+            version: CACHE_VERSION,
+            last_modified: None,
             indexes: HashMap::new(),
             artists: HashMap::new(),
             albums: HashMap::new(),
             songs: HashMap::new(),
+            album_lists: HashMap::new(),
+            genres: None,
+            genre_songs: HashMap::new(),
+            starred: None,
+            random_songs: None,
+            playlists: None,
+            playlist_items: HashMap::new(),
+            playlist_songs: HashMap::new(),
+            artist_infos: HashMap::new(),
+        }
+    }
+
+    pub fn load(path: &Path) -> Result<LibraryCache> {
+        let cache: LibraryCache = serde_json::from_reader(BufReader::new(File::open(path)?))?;
+        if cache.version != CACHE_VERSION {
+            bail!("unsupported cache version {}", cache.version);
+        }
+        Ok(cache)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
         }
+        // write to a temporary file first, so that an interrupted write keeps the old cache
+        let tmp_path = path.with_extension("tmp");
+        let mut writer = BufWriter::new(File::create(&tmp_path)?);
+        serde_json::to_writer(&mut writer, self)?;
+        writer.flush()?;
+        fs::rename(tmp_path, path)?;
+        Ok(())
     }
 
+    pub fn last_modified(&self) -> Option<u64> {
+        self.last_modified
+    }
+
+    /// Check the response to `getIndexes` for changes to the artist collection since the last
+    /// check. If it has changed, the artist index is dropped, so that it is fetched again along
+    /// with the album lists of all artists, and `update_root` removes the artists no longer on
+    /// the server. Albums keep their songs unless `update_artist` finds them changed. Returns
+    /// whether the cache was invalidated.
+    pub fn update_indexes(&mut self, resp: SubsonicResponse) -> bool {
+        if let Some(SubsonicData::Indexes(indexes)) = resp.data {
+            if self
+                .last_modified
+                .is_none_or(|last_modified| indexes.last_modified > last_modified)
+            {
+                self.indexes.clear();
+                self.last_modified = Some(indexes.last_modified);
+                return true;
+            }
+        }
+        false
+    }
+
+    /// Drop the cached children of an item, so that they are fetched again.
+    pub fn invalidate(&mut self, key: &LibraryItemKey) {
+        match key {
+            LibraryItemKey::Root => self.indexes.clear(),
+            LibraryItemKey::AlbumList(list_type) => {
+                self.album_lists.remove(list_type);
+            }
+            LibraryItemKey::Genres => self.genres = None,
+            LibraryItemKey::Starred => self.starred = None,
+            LibraryItemKey::RandomSongs => self.random_songs = None,
+            LibraryItemKey::Playlists => self.playlists = None,
+            LibraryItemKey::Playlist(playlist_id) => {
+                self.playlist_songs.remove(playlist_id);
+            }
+            LibraryItemKey::GenreSongs(genre) => {
+                self.genre_songs.remove(genre);
+            }
+            LibraryItemKey::Decades | LibraryItemKey::Genre(_) => {}
+            LibraryItemKey::Artist(artist_id) => {
+                if let Some(artist_entry) = self.artists.get_mut(artist_id) {
+                    artist_entry.children.clear();
+                }
+                self.artist_infos.remove(artist_id);
+            }
+            LibraryItemKey::Album(album_id) => {
+                if let Some(album_entry) = self.albums.get_mut(album_id) {
+                    album_entry.children.clear();
+                }
+            }
+            LibraryItemKey::Song(_) => {}
+        }
+    }
+
+    /// Replace the artist index. Artists missing from it have been removed on the server, they
+    /// are dropped along with their albums and songs.
     pub fn update_root(&mut self, resp: SubsonicResponse) {
         if let Some(SubsonicData::Artists(artists)) = resp.data {
+            let artist_ids: HashSet<_> = artists
+                .index
+                .iter()
+                .flat_map(|index| &index.artist)
+                .map(|artist| &artist.id)
+                .collect();
+            let removed_artists: HashSet<_> = self
+                .artists
+                .keys()
+                .filter(|id| !artist_ids.contains(id))
+                .cloned()
+                .collect();
+            let removed_albums: HashSet<_> = self
+                .albums
+                .iter()
+                .filter(|(_, album)| {
+                    album
+                        .parent
+                        .as_ref()
+                        .is_some_and(|artist_id| removed_artists.contains(artist_id))
+                })
+                .map(|(id, _)| id.clone())
+                .collect();
+            self.artists.retain(|id, _| !removed_artists.contains(id));
+            self.albums.retain(|id, _| !removed_albums.contains(id));
+            self.songs.retain(|_, song| {
+                song.parent
+                    .as_ref()
+                    .is_none_or(|album_id| !removed_albums.contains(album_id))
+            });
+
             for index in artists.index {
                 let mut index_artists = HashSet::new();
                 for artist in index.artist {
                     index_artists.insert(LibraryItemKey::Artist(artist.id.clone()));
                     self.artists.insert(
-                        artist.id,
+                        artist.id.clone(),
                         CacheEntry {
                             parent: None,
                             children: vec![],
-                            item: Artist {
-                                name: artist.name.as_str().into(),
-                            },
+                            item: Artist::from(&artist),
                         },
                     );
                 }
@@ -46,28 +212,31 @@ impl LibraryCache {
     pub fn update_artist(&mut self, resp: SubsonicResponse, artist_id: &Id) {
         if let Some(SubsonicData::Artist(artist)) = resp.data {
             let mut album_ids = vec![];
-            for album in artist.album {
+            for album in &artist.album {
                 album_ids.push(album.id.clone());
+                // keep the songs of albums which have not changed since they were cached
+                let song_ids = match self.albums.get(&album.id) {
+                    Some(album_entry) if album_entry.item.created == album.created => {
+                        album_entry.children.clone()
+                    }
+                    _ => vec![],
+                };
                 self.albums.insert(
-                    album.id,
+                    album.id.clone(),
                     CacheEntry {
-                        item: Album {
-                            name: album.name.as_str().into(),
-                        },
+                        item: Album::from(album),
                         parent: Some(artist_id.clone()),
-                        children: vec![],
+                        children: song_ids,
                     },
                 );
             }
 
             self.artists.insert(
-                artist.id,
+                artist.id.clone(),
                 CacheEntry {
                     parent: None,
                     children: album_ids,
-                    item: Artist {
-                        name: artist.name.as_str().into(),
-                    },
+                    item: Artist::from(&artist),
                 },
             );
         }
@@ -76,57 +245,526 @@ impl LibraryCache {
     pub fn update_album(&mut self, resp: SubsonicResponse, album_id: &Id) {
         if let Some(SubsonicData::Album(album)) = resp.data {
             let mut song_ids = vec![];
-            for song in album.song {
+            for song in &album.song {
                 song_ids.push(song.id.clone());
                 self.songs.insert(
-                    song.id,
+                    song.id.clone(),
                     CacheEntry {
-                        item: Song {
-                            title: song.title.as_str().into(),
-                            track_number: song.track,
-                            duration: song.duration,
-                        },
+                        item: Song::from(song),
                         parent: Some(album_id.clone()),
                         children: vec![],
                     },
                 );
             }
 
+            let parent = album.artist_id.clone().or_else(|| {
+                self.albums
+                    .get(&album.id)
+                    .and_then(|album_entry| album_entry.parent.clone())
+            });
             self.albums.insert(
-                album.id,
+                album.id.clone(),
                 CacheEntry {
-                    parent: None,
+                    parent,
                     children: song_ids,
-                    item: Album {
-                        name: album.name.as_str().into(),
-                    },
+                    item: Album::from(&album),
                 },
             );
         }
     }
 
+    /// Append a page of an album list. A page with less than `page_size` albums completes
+    /// the list.
+    pub fn update_album_list(
+        &mut self,
+        resp: SubsonicResponse,
+        list_type: &AlbumListType,
+        page_size: usize,
+    ) {
+        if let Some(SubsonicData::AlbumList2(list)) = resp.data {
+            let complete = list.album.len() < page_size;
+            let mut album_ids = vec![];
+            for album in list.album {
+                album_ids.push(album.id.clone());
+                self.insert_album(album);
+            }
+
+            let list_entry = self.album_lists.entry(list_type.clone()).or_default();
+            list_entry.ids.extend(album_ids);
+            list_entry.complete = complete;
+        }
+    }
+
+    /// Add an album listed without its songs, keeping the cached songs if it is unchanged.
+    fn insert_album(&mut self, album: subsonic::Album) {
+        let song_ids = match self.albums.get(&album.id) {
+            Some(album_entry) if album_entry.item.created == album.created => {
+                album_entry.children.clone()
+            }
+            _ => vec![],
+        };
+        self.albums.insert(
+            album.id.clone(),
+            CacheEntry {
+                item: Album::from(&album),
+                parent: album.artist_id.clone(),
+                children: song_ids,
+            },
+        );
+    }
+
+    pub fn update_genres(&mut self, resp: SubsonicResponse) {
+        if let Some(SubsonicData::Genres(genres)) = resp.data {
+            self.genres = Some(
+                genres
+                    .genre
+                    .into_iter()
+                    .map(|genre| Genre {
+                        name: genre.name,
+                        album_count: genre.album_count,
+                        song_count: genre.song_count,
+                    })
+                    .collect(),
+            );
+        }
+    }
+
+    /// Append a page of the songs of a genre. A page with less than `page_size` songs
+    /// completes the list.
+    pub fn update_genre_songs(&mut self, resp: SubsonicResponse, genre: &str, page_size: usize) {
+        if let Some(SubsonicData::SongsByGenre(songs)) = resp.data {
+            let complete = songs.song.len() < page_size;
+            let mut song_ids = vec![];
+            for song in songs.song {
+                song_ids.push(song.id.clone());
+                self.songs.insert(
+                    song.id.clone(),
+                    CacheEntry {
+                        item: Song::from(&song),
+                        parent: song.album_id,
+                        children: vec![],
+                    },
+                );
+            }
+
+            let list_entry = self.genre_songs.entry(genre.to_string()).or_default();
+            list_entry.ids.extend(song_ids);
+            list_entry.complete = complete;
+        }
+    }
+
+    /// Replace the starred items. Cached items keep their children.
+    pub fn update_starred(&mut self, resp: SubsonicResponse) {
+        if let Some(SubsonicData::Starred2(starred)) = resp.data {
+            let mut keys = vec![];
+            for artist in starred.artist {
+                keys.push(LibraryItemKey::Artist(artist.id.clone()));
+                self.artists
+                    .entry(artist.id.clone())
+                    .and_modify(|artist_entry| artist_entry.item = Artist::from(&artist))
+                    .or_insert_with(|| CacheEntry {
+                        parent: None,
+                        children: vec![],
+                        item: Artist::from(&artist),
+                    });
+            }
+            for album in starred.album {
+                keys.push(LibraryItemKey::Album(album.id.clone()));
+                self.insert_album(album);
+            }
+            for song in starred.song {
+                keys.push(LibraryItemKey::Song(song.id.clone()));
+                self.songs.insert(
+                    song.id.clone(),
+                    CacheEntry {
+                        item: Song::from(&song),
+                        parent: song.album_id,
+                        children: vec![],
+                    },
+                );
+            }
+            self.starred = Some(keys);
+        }
+    }
+
+    /// Replace the songs picked at random.
+    pub fn update_random_songs(&mut self, resp: SubsonicResponse) {
+        if let Some(SubsonicData::RandomSongs(songs)) = resp.data {
+            let mut song_ids = vec![];
+            for song in songs.song {
+                song_ids.push(song.id.clone());
+                self.songs.insert(
+                    song.id.clone(),
+                    CacheEntry {
+                        item: Song::from(&song),
+                        parent: song.album_id,
+                        children: vec![],
+                    },
+                );
+            }
+            self.random_songs = Some(song_ids);
+        }
+    }
+
+    /// Replace the list of playlists. The songs of changed playlists are dropped.
+    pub fn update_playlists(&mut self, resp: SubsonicResponse) {
+        if let Some(SubsonicData::Playlists(playlists)) = resp.data {
+            let mut playlist_ids = vec![];
+            for playlist in &playlists.playlist {
+                playlist_ids.push(playlist.id.clone());
+                if self
+                    .playlist_items
+                    .get(&playlist.id)
+                    .is_some_and(|item| item.changed != playlist.changed)
+                {
+                    self.playlist_songs.remove(&playlist.id);
+                }
+                self.playlist_items
+                    .insert(playlist.id.clone(), Playlist::from(playlist));
+            }
+            self.playlists = Some(playlist_ids);
+        }
+    }
+
+    pub fn update_playlist(&mut self, resp: SubsonicResponse) {
+        if let Some(SubsonicData::Playlist(playlist)) = resp.data {
+            let mut song_ids = vec![];
+            for song in &playlist.entry {
+                song_ids.push(song.id.clone());
+                self.songs.insert(
+                    song.id.clone(),
+                    CacheEntry {
+                        item: Song::from(song),
+                        parent: song.album_id.clone(),
+                        children: vec![],
+                    },
+                );
+            }
+            self.playlist_items
+                .insert(playlist.id.clone(), Playlist::from(&playlist));
+            self.playlist_songs.insert(playlist.id, song_ids);
+        }
+    }
+
+    pub fn get_playlist_songs(&self, playlist_id: &Id) -> Option<&[Id]> {
+        self.playlist_songs.get(playlist_id).map(Vec::as_slice)
+    }
+
+    pub fn get_marks(&self, key: &LibraryItemKey) -> Option<Marks> {
+        match key {
+            LibraryItemKey::Artist(id) => self.artists.get(id).map(|entry| entry.item.marks),
+            LibraryItemKey::Album(id) => self.albums.get(id).map(|entry| entry.item.marks),
+            LibraryItemKey::Song(id) => self.songs.get(id).map(|entry| entry.item.marks),
+            _ => None,
+        }
+    }
+
+    /// Change the marks of a cached item, keeping the starred items up to date.
+    pub fn set_marks(&mut self, key: &LibraryItemKey, marks: Marks) {
+        let item_marks = match key {
+            LibraryItemKey::Artist(id) => self.artists.get_mut(id).map(|e| &mut e.item.marks),
+            LibraryItemKey::Album(id) => self.albums.get_mut(id).map(|e| &mut e.item.marks),
+            LibraryItemKey::Song(id) => self.songs.get_mut(id).map(|e| &mut e.item.marks),
+            _ => None,
+        };
+        if let Some(item_marks) = item_marks {
+            *item_marks = marks;
+            if let Some(starred) = &mut self.starred {
+                starred.retain(|starred_key| starred_key != key);
+                if marks.starred {
+                    starred.push(key.clone());
+                }
+            }
+        }
+    }
+
+    /// Number of children of a paged item loaded so far.
+    pub fn list_len(&self, key: &LibraryItemKey) -> usize {
+        self.list_entry(key)
+            .map(|list_entry| list_entry.ids.len())
+            .unwrap_or_default()
+    }
+
+    /// Whether further pages of the item can be loaded.
+    pub fn has_more(&self, key: &LibraryItemKey) -> bool {
+        self.list_entry(key)
+            .is_some_and(|list_entry| !list_entry.complete)
+    }
+
+    fn list_entry(&self, key: &LibraryItemKey) -> Option<&ListEntry> {
+        match key {
+            LibraryItemKey::AlbumList(list_type) => self.album_lists.get(list_type),
+            LibraryItemKey::GenreSongs(genre) => self.genre_songs.get(genre),
+            _ => None,
+        }
+    }
+
+    /// Add the items found by a search. Items already in the cache are kept, as they may
+    /// have their children cached already.
+    pub fn update_search(&mut self, resp: SubsonicResponse) {
+        if let Some(SubsonicData::SearchResult3(result)) = resp.data {
+            for artist in result.artist {
+                self.artists
+                    .entry(artist.id.clone())
+                    .or_insert_with(|| CacheEntry {
+                        parent: None,
+                        children: vec![],
+                        item: Artist::from(&artist),
+                    });
+            }
+            for album in result.album {
+                self.albums
+                    .entry(album.id.clone())
+                    .or_insert_with(|| CacheEntry {
+                        parent: album.artist_id.clone(),
+                        children: vec![],
+                        item: Album::from(&album),
+                    });
+            }
+            for song in result.song {
+                self.songs
+                    .entry(song.id.clone())
+                    .or_insert_with(|| CacheEntry {
+                        parent: song.album_id.clone(),
+                        children: vec![],
+                        item: Song::from(&song),
+                    });
+            }
+        }
+    }
+
+    /// Store the responses to `getArtistInfo2` and `getTopSongs` for an artist. Similar
+    /// artists and top songs are added to the cache like search results.
+    pub fn update_artist_info(
+        &mut self,
+        artist_id: &Id,
+        info_resp: Option<SubsonicResponse>,
+        top_songs_resp: Option<SubsonicResponse>,
+    ) {
+        let mut entry = ArtistInfoEntry::default();
+        if let Some(SubsonicData::ArtistInfo2(info)) = info_resp.and_then(|resp| resp.data) {
+            entry.biography = info.biography.as_deref().map(strip_html);
+            entry.music_brainz_id = info.music_brainz_id;
+            for artist in info.similar_artist {
+                entry.similar.push(artist.id.clone());
+                self.artists
+                    .entry(artist.id.clone())
+                    .or_insert_with(|| CacheEntry {
+                        parent: None,
+                        children: vec![],
+                        item: Artist::from(&artist),
+                    });
+            }
+        }
+        if let Some(SubsonicData::TopSongs(songs)) = top_songs_resp.and_then(|resp| resp.data) {
+            for song in songs.song {
+                entry.top_songs.push(song.id.clone());
+                self.songs
+                    .entry(song.id.clone())
+                    .or_insert_with(|| CacheEntry {
+                        parent: song.album_id.clone(),
+                        children: vec![],
+                        item: Song::from(&song),
+                    });
+            }
+        }
+        self.artist_infos.insert(artist_id.clone(), entry);
+    }
+
+    pub fn get_artist_info(&self, artist_id: &Id) -> Option<ArtistInfo> {
+        let entry = self.artist_infos.get(artist_id)?;
+        let item = |key: LibraryItemKey| self.get_item(&key).map(|item| (key, item));
+        Some(ArtistInfo {
+            biography: entry.biography.clone(),
+            music_brainz_id: entry.music_brainz_id.clone(),
+            similar: entry
+                .similar
+                .iter()
+                .cloned()
+                .map(LibraryItemKey::Artist)
+                .filter_map(item)
+                .collect(),
+            top_songs: entry
+                .top_songs
+                .iter()
+                .cloned()
+                .map(LibraryItemKey::Song)
+                .filter_map(item)
+                .collect(),
+        })
+    }
+
+    /// Find all cached items of the given type whose name contains the query.
+    pub fn find(
+        &self,
+        find_type: FindType,
+        query: &SearchString,
+    ) -> Vec<(LibraryItemKey, LibraryItem)> {
+        let mut artists: Vec<_> = self
+            .artists
+            .iter()
+            .filter(|(_, entry)| {
+                find_type.includes(FindType::Artist) && entry.item.name.contains(query)
+            })
+            .map(|(id, entry)| {
+                (
+                    LibraryItemKey::Artist(id.clone()),
+                    LibraryItem::Artist(entry.item.clone()),
+                )
+            })
+            .collect();
+        let mut albums: Vec<_> = self
+            .albums
+            .iter()
+            .filter(|(_, entry)| {
+                find_type.includes(FindType::Album) && entry.item.name.contains(query)
+            })
+            .map(|(id, entry)| {
+                (
+                    LibraryItemKey::Album(id.clone()),
+                    LibraryItem::Album(entry.item.clone()),
+                )
+            })
+            .collect();
+        let mut songs: Vec<_> = self
+            .songs
+            .iter()
+            .filter(|(_, entry)| {
+                find_type.includes(FindType::Song) && entry.item.title.contains(query)
+            })
+            .map(|(id, entry)| {
+                (
+                    LibraryItemKey::Song(id.clone()),
+                    LibraryItem::Song(entry.item.clone()),
+                )
+            })
+            .collect();
+
+        artists.sort_by_key(|(_, item)| item.to_string());
+        albums.sort_by_key(|(_, item)| item.to_string());
+        songs.sort_by_key(|(_, item)| item.to_string());
+        artists.extend(albums);
+        artists.extend(songs);
+        artists
+    }
+
     pub(crate) fn get_children(
         &self,
         key: &LibraryItemKey,
     ) -> Option<Vec<(LibraryItemKey, LibraryItem)>> {
         match key {
             LibraryItemKey::Root => {
-                if self.artists.is_empty() {
+                if self.indexes.is_empty() {
                     None
                 } else {
                     Some(
-                        self.artists
-                            .iter()
-                            .map(|(k, v)| {
-                                (
-                                    LibraryItemKey::Artist(k.clone()),
-                                    LibraryItem::Artist(v.item.clone()),
-                                )
+                        self.indexes
+                            .values()
+                            .flatten()
+                            .filter_map(|artist_key| match artist_key {
+                                LibraryItemKey::Artist(artist_id) => {
+                                    self.artists.get(artist_id).map(|artist_entry| {
+                                        (
+                                            artist_key.clone(),
+                                            LibraryItem::Artist(artist_entry.item.clone()),
+                                        )
+                                    })
+                                }
+                                _ => None,
                             })
                             .collect(),
                     )
                 }
             }
+            LibraryItemKey::AlbumList(list_type) => {
+                self.album_lists.get(list_type).map(|list_entry| {
+                    list_entry
+                        .ids
+                        .iter()
+                        .filter_map(|album_id| {
+                            self.albums.get(album_id).map(|album_entry| {
+                                (
+                                    LibraryItemKey::Album(album_id.clone()),
+                                    LibraryItem::Album(album_entry.item.clone()),
+                                )
+                            })
+                        })
+                        .collect()
+                })
+            }
+            LibraryItemKey::Genres => self.genres.as_ref().map(|genres| {
+                genres
+                    .iter()
+                    .map(|genre| {
+                        (
+                            LibraryItemKey::Genre(genre.name.clone()),
+                            LibraryItem::Genre(genre.clone()),
+                        )
+                    })
+                    .collect()
+            }),
+            LibraryItemKey::GenreSongs(genre) => self.genre_songs.get(genre).map(|list_entry| {
+                list_entry
+                    .ids
+                    .iter()
+                    .filter_map(|song_id| {
+                        self.songs.get(song_id).map(|song_entry| {
+                            (
+                                LibraryItemKey::Song(song_id.clone()),
+                                LibraryItem::Song(song_entry.item.clone()),
+                            )
+                        })
+                    })
+                    .collect()
+            }),
+            LibraryItemKey::Starred => self.starred.as_ref().map(|starred| {
+                starred
+                    .iter()
+                    .filter_map(|key| self.get_item(key).map(|item| (key.clone(), item)))
+                    .collect()
+            }),
+            LibraryItemKey::RandomSongs => self.random_songs.as_ref().map(|song_ids| {
+                song_ids
+                    .iter()
+                    .filter_map(|song_id| {
+                        self.get_song(song_id).map(|song| {
+                            (
+                                LibraryItemKey::Song(song_id.clone()),
+                                LibraryItem::Song(song),
+                            )
+                        })
+                    })
+                    .collect()
+            }),
+            LibraryItemKey::Playlists => self.playlists.as_ref().map(|playlist_ids| {
+                playlist_ids
+                    .iter()
+                    .filter_map(|playlist_id| {
+                        self.playlist_items.get(playlist_id).map(|playlist| {
+                            (
+                                LibraryItemKey::Playlist(playlist_id.clone()),
+                                LibraryItem::Playlist(playlist.clone()),
+                            )
+                        })
+                    })
+                    .collect()
+            }),
+            LibraryItemKey::Playlist(playlist_id) => {
+                self.playlist_songs.get(playlist_id).map(|song_ids| {
+                    song_ids
+                        .iter()
+                        .filter_map(|song_id| {
+                            self.songs.get(song_id).map(|song_entry| {
+                                (
+                                    LibraryItemKey::Song(song_id.clone()),
+                                    LibraryItem::Song(song_entry.item.clone()),
+                                )
+                            })
+                        })
+                        .collect()
+                })
+            }
+            LibraryItemKey::Decades | LibraryItemKey::Genre(_) => None,
             LibraryItemKey::Artist(artist_id) => {
                 if let Some(artist_entry) = self.artists.get(artist_id) {
                     if artist_entry.children.is_empty() {
@@ -152,7 +790,7 @@ impl LibraryCache {
                 }
             }
             LibraryItemKey::Album(album_id) => {
-                if let Some(album_entry) = self.artists.get(album_id) {
+                if let Some(album_entry) = self.albums.get(album_id) {
                     if album_entry.children.is_empty() {
                         None
                     } else {
@@ -178,10 +816,586 @@ impl LibraryCache {
             LibraryItemKey::Song(_) => None,
         }
     }
+
+    pub fn get_item(&self, key: &LibraryItemKey) -> Option<LibraryItem> {
+        match key {
+            LibraryItemKey::Root => None,
+            LibraryItemKey::AlbumList(list_type) => Some(LibraryItem::Node(list_type.to_string())),
+            LibraryItemKey::Decades => Some(LibraryItem::Node(DECADES_TITLE.to_string())),
+            LibraryItemKey::Genres => Some(LibraryItem::Node(GENRES_TITLE.to_string())),
+            LibraryItemKey::Starred => Some(LibraryItem::Node(STARRED_TITLE.to_string())),
+            LibraryItemKey::RandomSongs => Some(LibraryItem::Node(RANDOM_SONGS_TITLE.to_string())),
+            LibraryItemKey::Playlists => Some(LibraryItem::Node(PLAYLISTS_TITLE.to_string())),
+            LibraryItemKey::Playlist(playlist_id) => self
+                .playlist_items
+                .get(playlist_id)
+                .map(|playlist| LibraryItem::Playlist(playlist.clone())),
+            LibraryItemKey::Genre(name) => self
+                .genres
+                .iter()
+                .flatten()
+                .find(|genre| genre.name == *name)
+                .map(|genre| LibraryItem::Genre(genre.clone()))
+                .or_else(|| Some(LibraryItem::Node(name.clone()))),
+            LibraryItemKey::GenreSongs(genre) => {
+                Some(LibraryItem::Node(format!("{} songs", genre)))
+            }
+            LibraryItemKey::Artist(artist_id) => self
+                .artists
+                .get(artist_id)
+                .map(|artist_entry| LibraryItem::Artist(artist_entry.item.clone())),
+            LibraryItemKey::Album(album_id) => self
+                .albums
+                .get(album_id)
+                .map(|album_entry| LibraryItem::Album(album_entry.item.clone())),
+            LibraryItemKey::Song(song_id) => self.get_song(song_id).map(LibraryItem::Song),
+        }
+    }
+
+    pub fn get_parent(&self, key: &LibraryItemKey) -> Option<LibraryItemKey> {
+        match key {
+            LibraryItemKey::Root => None,
+            LibraryItemKey::AlbumList(AlbumListType::ByYear(..)) => Some(LibraryItemKey::Decades),
+            LibraryItemKey::AlbumList(AlbumListType::ByGenre(genre))
+            | LibraryItemKey::GenreSongs(genre) => Some(LibraryItemKey::Genre(genre.clone())),
+            LibraryItemKey::Genre(_) => Some(LibraryItemKey::Genres),
+            LibraryItemKey::AlbumList(_)
+            | LibraryItemKey::Decades
+            | LibraryItemKey::Genres
+            | LibraryItemKey::Starred
+            | LibraryItemKey::RandomSongs
+            | LibraryItemKey::Playlists
+            | LibraryItemKey::Artist(_) => Some(LibraryItemKey::Root),
+            LibraryItemKey::Playlist(_) => Some(LibraryItemKey::Playlists),
+            LibraryItemKey::Album(album_id) => Some(
+                self.albums
+                    .get(album_id)
+                    .and_then(|album_entry| album_entry.parent.clone())
+                    .map(LibraryItemKey::Artist)
+                    .unwrap_or(LibraryItemKey::Root),
+            ),
+            LibraryItemKey::Song(song_id) => self
+                .songs
+                .get(song_id)
+                .and_then(|song_entry| song_entry.parent.clone())
+                .map(LibraryItemKey::Album),
+        }
+    }
+
+    pub fn get_song(&self, song_id: &Id) -> Option<Song> {
+        self.songs
+            .get(song_id)
+            .map(|song_entry| song_entry.item.clone())
+    }
 }
 
+#[derive(Serialize, Deserialize)]
 struct CacheEntry<T> {
     parent: Option<Id>,
     children: Vec<Id>,
     item: T,
 }
+
+#[derive(Default)]
+struct ArtistInfoEntry {
+    biography: Option<String>,
+    music_brainz_id: Option<String>,
+    similar: Vec<Id>,
+    top_songs: Vec<Id>,
+}
+
+/// Plain text of a biography from Last.fm, which ends with a link to the artist page.
+fn strip_html(text: &str) -> String {
+    let mut plain = String::new();
+    let mut in_tag = false;
+    for c in text.chars() {
+        match c {
+            '<' => in_tag = true,
+            '>' if in_tag => in_tag = false,
+            c if !in_tag => plain.push(c),
+            _ => {}
+        }
+    }
+    plain
+        .replace("&quot;", "\"")
+        .replace("&#39;", "'")
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&amp;", "&")
+        .trim()
+        .to_string()
+}
+
+#[derive(Default)]
+struct ListEntry {
+    ids: Vec<Id>,
+    // whether the last page has been fetched
+    complete: bool,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::LibraryCache;
+    use crate::{
+        conn::AlbumListType,
+        library::{request::FindType, LibraryItemKey, Marks, SearchString},
+        subsonic::SubsonicResponse,
+    };
+
+    const SEARCH_RESULT: &str = r#"<subsonic-response xmlns="http://subsonic.org/restapi" status="ok" version="1.16.1">
+        <searchResult3>
+            <artist id="ar1" name="The Beatles" albumCount="2"/>
+            <album id="al1" name="Beatles for Sale" artist="The Beatles" artistId="ar1" songCount="14" duration="2000" created="2021-01-01T00:00:00Z"/>
+            <song id="s1" parent="al1" albumId="al1" isDir="false" title="Eight Days a Week" track="8" duration="164"/>
+            <song id="s2" parent="al1" albumId="al1" isDir="false" title="Rock and Roll Music" track="4" duration="151"/>
+        </searchResult3>
+    </subsonic-response>"#;
+
+    #[test]
+    fn find() {
+        let mut cache = LibraryCache::new();
+        cache.update_search(serde_xml_rs::from_str(SEARCH_RESULT).unwrap());
+
+        let keys = |find_type, query: &str| -> Vec<LibraryItemKey> {
+            cache
+                .find(find_type, &SearchString::from(query))
+                .into_iter()
+                .map(|(key, _)| key)
+                .collect()
+        };
+
+        assert_eq!(
+            keys(FindType::Any, "beatles"),
+            [
+                LibraryItemKey::Artist("ar1".into()),
+                LibraryItemKey::Album("al1".into())
+            ]
+        );
+        assert!(keys(FindType::Album, "ROCK").is_empty());
+        assert_eq!(
+            keys(FindType::Song, "ROCK"),
+            [LibraryItemKey::Song("s2".into())]
+        );
+
+        // searched items do not make up the artist index
+        assert!(cache.get_children(&LibraryItemKey::Root).is_none());
+    }
+
+    fn artist_response(created: &str) -> SubsonicResponse {
+        serde_xml_rs::from_str(&format!(
+            r#"<subsonic-response xmlns="http://subsonic.org/restapi" status="ok" version="1.16.1">
+                <artist id="ar1" name="The Beatles" albumCount="1">
+                    <album id="al1" name="Beatles for Sale" artistId="ar1" songCount="1" duration="164" created="{}"/>
+                </artist>
+            </subsonic-response>"#,
+            created
+        ))
+        .unwrap()
+    }
+
+    const ALBUM: &str = r#"<subsonic-response xmlns="http://subsonic.org/restapi" status="ok" version="1.16.1">
+        <album id="al1" name="Beatles for Sale" artistId="ar1" songCount="1" duration="164" created="2021-01-01T00:00:00Z">
+            <song id="s1" parent="al1" albumId="al1" isDir="false" title="Eight Days a Week" track="8" duration="164"/>
+        </album>
+    </subsonic-response>"#;
+
+    fn indexes_response(last_modified: u64) -> SubsonicResponse {
+        serde_xml_rs::from_str(&format!(
+            r#"<subsonic-response xmlns="http://subsonic.org/restapi" status="ok" version="1.16.1">
+                <indexes lastModified="{}" ignoredArticles="The"/>
+            </subsonic-response>"#,
+            last_modified
+        ))
+        .unwrap()
+    }
+
+    #[test]
+    fn changed_album() {
+        let album_key = LibraryItemKey::Album("al1".into());
+        let mut cache = LibraryCache::new();
+        cache.update_artist(artist_response("2021-01-01T00:00:00Z"), &"ar1".into());
+        cache.update_album(serde_xml_rs::from_str(ALBUM).unwrap(), &"al1".into());
+        assert_eq!(cache.get_children(&album_key).unwrap().len(), 1);
+
+        // unchanged albums keep their songs when the artist is fetched again
+        cache.update_artist(artist_response("2021-01-01T00:00:00Z"), &"ar1".into());
+        assert_eq!(cache.get_children(&album_key).unwrap().len(), 1);
+
+        cache.update_artist(artist_response("2022-01-01T00:00:00Z"), &"ar1".into());
+        assert!(cache.get_children(&album_key).is_none());
+    }
+
+    #[test]
+    fn changed_indexes() {
+        let mut cache = LibraryCache::new();
+        assert!(cache.update_indexes(indexes_response(1000)));
+        assert_eq!(cache.last_modified(), Some(1000));
+        assert!(!cache.update_indexes(indexes_response(1000)));
+        assert!(cache.update_indexes(indexes_response(2000)));
+    }
+
+    #[test]
+    fn removed_artists() {
+        let mut cache = LibraryCache::new();
+        cache.update_artist(artist_response("2021-01-01T00:00:00Z"), &"ar1".into());
+        cache.update_album(serde_xml_rs::from_str(ALBUM).unwrap(), &"al1".into());
+        let artists = |ids: &[&str]| -> SubsonicResponse {
+            let artists: String = ids
+                .iter()
+                .map(|id| {
+                    format!(
+                        r#"<artist id="{}" name="Artist {}" albumCount="1"/>"#,
+                        id, id
+                    )
+                })
+                .collect();
+            serde_xml_rs::from_str(&format!(
+                r#"<subsonic-response xmlns="http://subsonic.org/restapi" status="ok" version="1.16.1">
+                    <artists ignoredArticles="The"><index name="A">{}</index></artists>
+                </subsonic-response>"#,
+                artists
+            ))
+            .unwrap()
+        };
+
+        cache.update_root(artists(&["ar1", "ar2"]));
+        assert!(cache.get_item(&LibraryItemKey::Song("s1".into())).is_some());
+
+        // the albums and songs of an artist removed on the server are dropped as well
+        cache.update_root(artists(&["ar2"]));
+        assert!(cache
+            .get_item(&LibraryItemKey::Artist("ar1".into()))
+            .is_none());
+        assert!(cache
+            .get_item(&LibraryItemKey::Album("al1".into()))
+            .is_none());
+        assert!(cache.get_item(&LibraryItemKey::Song("s1".into())).is_none());
+        assert!(cache
+            .get_item(&LibraryItemKey::Artist("ar2".into()))
+            .is_some());
+    }
+
+    #[test]
+    fn random_songs() {
+        let mut cache = LibraryCache::new();
+        cache.update_random_songs(
+            serde_xml_rs::from_str(
+                r#"<subsonic-response xmlns="http://subsonic.org/restapi" status="ok" version="1.16.1">
+                    <randomSongs>
+                        <song id="s1" parent="al1" albumId="al1" isDir="false" title="Eight Days a Week"/>
+                    </randomSongs>
+                </subsonic-response>"#,
+            )
+            .unwrap(),
+        );
+        let songs = cache.get_children(&LibraryItemKey::RandomSongs).unwrap();
+        assert_eq!(songs[0].0, LibraryItemKey::Song("s1".into()));
+
+        // a refresh draws new songs
+        cache.invalidate(&LibraryItemKey::RandomSongs);
+        assert!(cache.get_children(&LibraryItemKey::RandomSongs).is_none());
+    }
+
+    #[test]
+    fn save_load() {
+        let mut cache = LibraryCache::new();
+        cache.update_indexes(indexes_response(1000));
+        cache.update_artist(artist_response("2021-01-01T00:00:00Z"), &"ar1".into());
+        cache.update_album(serde_xml_rs::from_str(ALBUM).unwrap(), &"al1".into());
+
+        let path = std::env::temp_dir()
+            .join(format!("navicon-test-{}", std::process::id()))
+            .join("cache.json");
+        cache.save(&path).unwrap();
+        let loaded = LibraryCache::load(&path).unwrap();
+        std::fs::remove_dir_all(path.parent().unwrap()).unwrap();
+
+        assert_eq!(loaded.last_modified(), Some(1000));
+        let songs = loaded
+            .get_children(&LibraryItemKey::Album("al1".into()))
+            .unwrap();
+        assert_eq!(songs[0].1.to_string(), "8: Eight Days a Week [2:44]");
+        assert_eq!(
+            loaded.get_parent(&LibraryItemKey::Album("al1".into())),
+            Some(LibraryItemKey::Artist("ar1".into()))
+        );
+    }
+
+    fn album_list_response(album_ids: &[&str]) -> SubsonicResponse {
+        let albums: String = album_ids
+            .iter()
+            .map(|id| {
+                format!(
+                    r#"<album id="{}" name="Album {}" artistId="ar1" songCount="1" duration="164" created="2021-01-01T00:00:00Z"/>"#,
+                    id, id
+                )
+            })
+            .collect();
+        serde_xml_rs::from_str(&format!(
+            r#"<subsonic-response xmlns="http://subsonic.org/restapi" status="ok" version="1.16.1">
+                <albumList2>{}</albumList2>
+            </subsonic-response>"#,
+            albums
+        ))
+        .unwrap()
+    }
+
+    #[test]
+    fn album_list_pages() {
+        let list_type = AlbumListType::Newest;
+        let key = LibraryItemKey::AlbumList(list_type.clone());
+        let mut cache = LibraryCache::new();
+        assert!(cache.get_children(&key).is_none());
+
+        cache.update_album_list(album_list_response(&["al1", "al2"]), &list_type, 2);
+        assert!(cache.has_more(&key));
+        cache.update_album_list(album_list_response(&["al3"]), &list_type, 2);
+        assert!(!cache.has_more(&key));
+        assert_eq!(cache.list_len(&key), 3);
+
+        let names: Vec<_> = cache
+            .get_children(&key)
+            .unwrap()
+            .iter()
+            .map(|(_, item)| item.to_string())
+            .collect();
+        assert_eq!(names, ["Album al1", "Album al2", "Album al3"]);
+        assert_eq!(
+            cache.get_parent(&LibraryItemKey::Album("al2".into())),
+            Some(LibraryItemKey::Artist("ar1".into()))
+        );
+
+        cache.invalidate(&key);
+        assert!(cache.get_children(&key).is_none());
+    }
+
+    #[test]
+    fn genres() {
+        let mut cache = LibraryCache::new();
+        cache.update_genres(
+            serde_xml_rs::from_str(
+                r#"<subsonic-response xmlns="http://subsonic.org/restapi" status="ok" version="1.16.1">
+                    <genres>
+                        <genre songCount="28" albumCount="4">Rock</genre>
+                    </genres>
+                </subsonic-response>"#,
+            )
+            .unwrap(),
+        );
+        let genres = cache.get_children(&LibraryItemKey::Genres).unwrap();
+        assert_eq!(genres[0].0, LibraryItemKey::Genre("Rock".into()));
+        assert_eq!(genres[0].1.to_string(), "Rock [4 albums, 28 songs]");
+
+        let key = LibraryItemKey::GenreSongs("Rock".into());
+        cache.update_genre_songs(
+            serde_xml_rs::from_str(
+                r#"<subsonic-response xmlns="http://subsonic.org/restapi" status="ok" version="1.16.1">
+                    <songsByGenre>
+                        <song id="s1" parent="al1" albumId="al1" isDir="false" title="Eight Days a Week" genre="Rock"/>
+                    </songsByGenre>
+                </subsonic-response>"#,
+            )
+            .unwrap(),
+            "Rock",
+            2,
+        );
+        assert!(!cache.has_more(&key));
+        assert_eq!(cache.get_children(&key).unwrap().len(), 1);
+        assert_eq!(
+            cache.get_song(&"s1".into()).unwrap().genre.as_deref(),
+            Some("Rock")
+        );
+        assert_eq!(
+            cache.get_parent(&key),
+            Some(LibraryItemKey::Genre("Rock".into()))
+        );
+        assert_eq!(
+            cache.get_parent(&LibraryItemKey::AlbumList(AlbumListType::ByGenre(
+                "Rock".into()
+            ))),
+            Some(LibraryItemKey::Genre("Rock".into()))
+        );
+    }
+
+    #[test]
+    fn starred() {
+        let mut cache = LibraryCache::new();
+        cache.update_starred(
+            serde_xml_rs::from_str(
+                r#"<subsonic-response xmlns="http://subsonic.org/restapi" status="ok" version="1.16.1">
+                    <starred2>
+                        <album id="al1" name="Beatles for Sale" artist="The Beatles" artistId="ar1" songCount="14" duration="2000" created="2021-01-01T00:00:00Z" starred="2023-05-01T10:00:00Z" userRating="4"/>
+                        <song id="s1" parent="al1" albumId="al1" isDir="false" title="Eight Days a Week" starred="2023-05-01T10:00:00Z"/>
+                    </starred2>
+                </subsonic-response>"#,
+            )
+            .unwrap(),
+        );
+        let album = LibraryItemKey::Album("al1".into());
+        let song = LibraryItemKey::Song("s1".into());
+        assert_eq!(
+            cache.get_children(&LibraryItemKey::Starred).unwrap().len(),
+            2
+        );
+        assert_eq!(
+            cache.get_marks(&album),
+            Some(Marks {
+                starred: true,
+                rating: 4
+            })
+        );
+        assert_eq!(
+            cache.get_parent(&LibraryItemKey::Starred),
+            Some(LibraryItemKey::Root)
+        );
+
+        // unstarring removes the item from the starred items, the rating is kept
+        cache.set_marks(
+            &album,
+            Marks {
+                starred: false,
+                rating: 4,
+            },
+        );
+        let starred = cache.get_children(&LibraryItemKey::Starred).unwrap();
+        assert_eq!(starred.len(), 1);
+        assert_eq!(starred[0].0, song);
+        assert_eq!(cache.get_marks(&album).unwrap().rating, 4);
+
+        // rolled back
+        cache.set_marks(
+            &album,
+            Marks {
+                starred: true,
+                rating: 4,
+            },
+        );
+        assert_eq!(
+            cache.get_children(&LibraryItemKey::Starred).unwrap().len(),
+            2
+        );
+        assert_eq!(cache.get_marks(&LibraryItemKey::Root), None);
+    }
+
+    #[test]
+    fn playlists() {
+        let playlists = |changed| {
+            serde_xml_rs::from_str(&format!(
+                r#"<subsonic-response xmlns="http://subsonic.org/restapi" status="ok" version="1.16.1">
+                    <playlists>
+                        <playlist id="pl1" name="Road trip" owner="alice" public="true" songCount="2" duration="328" created="2024-03-01T12:00:00Z" changed="{}"/>
+                    </playlists>
+                </subsonic-response>"#,
+                changed
+            ))
+            .unwrap()
+        };
+        let mut cache = LibraryCache::new();
+        cache.update_playlists(playlists("2024-03-02T08:30:00Z"));
+        let key = LibraryItemKey::Playlist("pl1".into());
+        let children = cache.get_children(&LibraryItemKey::Playlists).unwrap();
+        assert_eq!(children[0].0, key);
+        assert_eq!(
+            children[0].1.to_string(),
+            "Road trip [2 songs, 5:28] by alice"
+        );
+        assert!(cache.get_children(&key).is_none());
+
+        cache.update_playlist(
+            serde_xml_rs::from_str(
+                r#"<subsonic-response xmlns="http://subsonic.org/restapi" status="ok" version="1.16.1">
+                    <playlist id="pl1" name="Road trip" owner="alice" public="true" songCount="2" duration="328" created="2024-03-01T12:00:00Z" changed="2024-03-02T08:30:00Z">
+                        <entry id="s1" parent="al1" albumId="al1" isDir="false" title="Eight Days a Week" duration="164"/>
+                        <entry id="s1" parent="al1" albumId="al1" isDir="false" title="Eight Days a Week" duration="164"/>
+                    </playlist>
+                </subsonic-response>"#,
+            )
+            .unwrap(),
+        );
+        assert_eq!(cache.get_children(&key).unwrap().len(), 2);
+        assert_eq!(
+            cache.get_playlist_songs(&"pl1".into()).unwrap(),
+            ["s1", "s1"]
+        );
+        assert_eq!(cache.get_parent(&key), Some(LibraryItemKey::Playlists));
+
+        // unchanged playlists keep their songs, changed ones are fetched again
+        cache.update_playlists(playlists("2024-03-02T08:30:00Z"));
+        assert!(cache.get_children(&key).is_some());
+        cache.update_playlists(playlists("2024-03-03T10:00:00Z"));
+        assert!(cache.get_children(&key).is_none());
+    }
+
+    #[test]
+    fn artist_info() {
+        let mut cache = LibraryCache::new();
+        let artist_id = "ar1".into();
+        cache.update_artist_info(
+            &artist_id,
+            Some(
+                serde_xml_rs::from_str(
+                    r#"<subsonic-response xmlns="http://subsonic.org/restapi" status="ok" version="1.16.1">
+                        <artistInfo2>
+                            <biography>The Beatles were a band &amp; more. &lt;a href="https://www.last.fm/music/The+Beatles"&gt;Read more on Last.fm&lt;/a&gt;</biography>
+                            <musicBrainzId>b10bbbfc-cf9e-42e0-be17-e2c3e1d2600d</musicBrainzId>
+                            <similarArtist id="ar3" name="Queen" albumCount="1"/>
+                        </artistInfo2>
+                    </subsonic-response>"#,
+                )
+                .unwrap(),
+            ),
+            Some(
+                serde_xml_rs::from_str(
+                    r#"<subsonic-response xmlns="http://subsonic.org/restapi" status="ok" version="1.16.1">
+                        <topSongs>
+                            <song id="s1" parent="al1" albumId="al1" isDir="false" title="Eight Days a Week" duration="164"/>
+                        </topSongs>
+                    </subsonic-response>"#,
+                )
+                .unwrap(),
+            ),
+        );
+
+        let info = cache.get_artist_info(&artist_id).unwrap();
+        assert_eq!(
+            info.biography.as_deref(),
+            Some("The Beatles were a band & more. Read more on Last.fm")
+        );
+        assert_eq!(
+            info.music_brainz_id.as_deref(),
+            Some("b10bbbfc-cf9e-42e0-be17-e2c3e1d2600d")
+        );
+        let similar: Vec<_> = info.similar.iter().map(|(key, _)| key.clone()).collect();
+        assert_eq!(similar, [LibraryItemKey::Artist("ar3".into())]);
+        let top_songs: Vec<_> = info.top_songs.iter().map(|(key, _)| key.clone()).collect();
+        assert_eq!(top_songs, [LibraryItemKey::Song("s1".into())]);
+        // the similar artist can be opened like any other artist
+        assert_eq!(
+            cache.get_parent(&LibraryItemKey::Artist("ar3".into())),
+            Some(LibraryItemKey::Root)
+        );
+
+        cache.invalidate(&LibraryItemKey::Artist(artist_id.clone()));
+        assert!(cache.get_artist_info(&artist_id).is_none());
+
+        // the top songs are kept if the artist info could not be fetched
+        cache.update_artist_info(
+            &artist_id,
+            None,
+            Some(
+                serde_xml_rs::from_str(
+                    r#"<subsonic-response xmlns="http://subsonic.org/restapi" status="ok" version="1.16.1">
+                        <topSongs>
+                            <song id="s1" parent="al1" albumId="al1" isDir="false" title="Eight Days a Week" duration="164"/>
+                        </topSongs>
+                    </subsonic-response>"#,
+                )
+                .unwrap(),
+            ),
+        );
+        let info = cache.get_artist_info(&artist_id).unwrap();
+        assert!(info.biography.is_none());
+        assert!(info.similar.is_empty());
+        assert_eq!(info.top_songs.len(), 1);
+    }
+}
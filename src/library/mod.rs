@@ -1,23 +1,73 @@
 mod cache;
 pub mod request;
+mod state;
 
-use std::sync::mpsc::{Receiver, Sender};
+use std::{
+    collections::VecDeque,
+    fs,
+    io::Read,
+    path::{Path, PathBuf},
+    sync::mpsc::{Receiver, SendError, Sender},
+    time::{Duration, SystemTime},
+};
 
-use crate::{conn::Connection, subsonic::Id, ui::event::UiEvent};
+use crate::{
+    conn::{AlbumListType, Connection, ConnectionError, Extension, StarTarget},
+    cover::{Cover, COVER_SIZE},
+    lyrics::Lyrics,
+    queue::{QueueEntry, SavedQueue},
+    settings::Profile,
+    subsonic::{self, Id, SubsonicData},
+    ui::event::{LibraryNode, UiEvent},
+};
 
-use self::{cache::LibraryCache, request::LibraryRequest};
+use self::{
+    cache::LibraryCache,
+    request::{FindType, LibraryRequest},
+    state::LibraryState,
+};
 
-use color_eyre::{eyre::bail, Result};
+use chrono::{DateTime, Datelike, Utc};
+use color_eyre::Result;
+use md5::Digest;
+use serde::{Deserialize, Serialize};
 
 pub struct Library {
+    profile: Profile,
     conn: Connection,
     cache: LibraryCache,
+    // location of the persisted cache, if any
+    cache_file: Option<PathBuf>,
+    state: LibraryState,
+    // location of the persisted state, if any
+    state_file: Option<PathBuf>,
     rx_request: Receiver<LibraryRequest>,
     tx_ui_event: Sender<UiEvent>,
+    // requests received while checking for superseded searches
+    pending: VecDeque<LibraryRequest>,
+    // messages which could not be logged because the UI has already quit
+    unreported: Vec<String>,
 }
 
+/// Maximum number of search results requested from the server per item type.
+const SEARCH_COUNT: usize = 50;
+/// Number of albums or songs requested per page of a paged item.
+const PAGE_SIZE: usize = 50;
+/// Number of similar artists and top songs shown with an artist.
+const ARTIST_INFO_COUNT: usize = 10;
+/// Size of the cached cover art above which the least recently used covers are removed.
+const COVER_CACHE_SIZE: u64 = 100 * 1024 * 1024;
+/// The oldest decade offered for browsing by year.
+const FIRST_DECADE: u32 = 1950;
+const DECADES_TITLE: &str = "Albums by year";
+const GENRES_TITLE: &str = "Genres";
+const STARRED_TITLE: &str = "Starred";
+const RANDOM_SONGS_TITLE: &str = "Random songs";
+const PLAYLISTS_TITLE: &str = "Playlists";
+
 impl Library {
     pub fn new(
+        profile: Profile,
         conn: Connection,
         rx_request: Receiver<LibraryRequest>,
         tx_ui_event: Sender<UiEvent>,
@@ -25,33 +75,435 @@ impl Library {
         Library {
             conn,
             cache: LibraryCache::new(),
+            cache_file: None,
+            state: LibraryState::default(),
+            state_file: None,
+            profile,
             rx_request,
             tx_ui_event,
+            pending: VecDeque::new(),
+            unreported: vec![],
         }
     }
 
-    pub fn run(&mut self) -> Result<()> {
+    /// Handle requests until shutdown. Returns the messages which could not be logged because
+    /// the UI had already quit, to be printed once the terminal is restored.
+    pub fn run(&mut self) -> Result<Vec<String>> {
+        self.connect()?;
+
         loop {
-            match self.rx_request.recv()? {
+            let request = match self.pending.pop_front() {
+                Some(request) => request,
+                None => self.rx_request.recv()?,
+            };
+            match request {
                 LibraryRequest::GetChildren(key) => {
-                    let children = self.get_children(&key)?;
+                    self.send_children(key)?;
+                }
+                LibraryRequest::Refresh(key) => {
+                    self.cache.invalidate(&key);
+                    self.send_children(key)?;
+                }
+                LibraryRequest::GetMore(key) => {
+                    if let Err(err) = self.fetch_page(&key) {
+                        self.tx_ui_event
+                            .send(UiEvent::LibraryRequestFailed(key, err.to_string()))?;
+                    } else {
+                        self.send_children(key)?;
+                    }
+                }
+                LibraryRequest::FindEntries(request_id, find_type, query) => {
+                    self.find_entries(request_id, find_type, &query)?;
+                }
+                LibraryRequest::GetSongs(key, mode) => match self.get_songs(&key) {
+                    Ok(songs) => {
+                        self.tx_ui_event
+                            .send(UiEvent::LibraryGetSongsComplete(mode, songs))?;
+                    }
+                    Err(err) => {
+                        self.tx_ui_event
+                            .send(UiEvent::LibraryRequestFailed(key, err.to_string()))?;
+                    }
+                },
+                LibraryRequest::SwitchProfile(profile, conn) => {
+                    if let Err(err) = self.save_cache() {
+                        self.tx_ui_event.send(UiEvent::AddLog(format!(
+                            "could not save library cache: {}",
+                            err
+                        )))?;
+                    }
+                    self.conn = conn;
+                    self.cache = LibraryCache::new();
+                    self.profile = profile;
+                    self.connect()?;
+                }
+                LibraryRequest::ToggleStar(key) => {
+                    self.update_marks(key, |marks| marks.starred = !marks.starred)?;
+                }
+                LibraryRequest::SetRating(key, rating) => {
+                    self.update_marks(key, |marks| marks.rating = rating)?;
+                }
+                LibraryRequest::GetMusicFolders => match self.get_music_folders() {
+                    Ok(music_folders) => {
+                        self.tx_ui_event.send(UiEvent::LibraryMusicFoldersComplete(
+                            music_folders,
+                            self.state.music_folder.clone(),
+                        ))?;
+                    }
+                    Err(err) => {
+                        self.tx_ui_event.send(UiEvent::AddLog(format!(
+                            "could not get music folders: {}",
+                            err
+                        )))?;
+                    }
+                },
+                LibraryRequest::SetMusicFolder(music_folder) => {
+                    self.set_music_folder(music_folder)?;
+                }
+                LibraryRequest::GetPlaylists => {
+                    self.cache.invalidate(&LibraryItemKey::Playlists);
+                    match self.get_children(&LibraryItemKey::Playlists) {
+                        Ok(playlists) => {
+                            let playlists = playlists
+                                .into_iter()
+                                .filter_map(|(key, item)| match key {
+                                    LibraryItemKey::Playlist(id) => Some((id, item.name())),
+                                    _ => None,
+                                })
+                                .collect();
+                            self.tx_ui_event
+                                .send(UiEvent::LibraryPlaylistsComplete(playlists))?;
+                        }
+                        Err(err) => {
+                            self.tx_ui_event.send(UiEvent::AddLog(format!(
+                                "could not get playlists: {}",
+                                err
+                            )))?;
+                        }
+                    }
+                }
+                LibraryRequest::AddToPlaylist(playlist_id, key) => {
+                    let result = self.add_to_playlist(playlist_id, &key);
+                    self.playlist_changed(result, None)?;
+                }
+                LibraryRequest::RemoveFromPlaylist(playlist_id, index) => {
+                    let result = self
+                        .conn
+                        .update_playlist(&playlist_id, None, &[], &[index])
+                        .map(|_| ());
+                    self.playlist_changed(result, Some(playlist_id))?;
+                }
+                LibraryRequest::MovePlaylistEntry(playlist_id, from, to) => {
+                    let result = self.move_playlist_entry(&playlist_id, from, to);
+                    self.playlist_changed(result, Some(playlist_id))?;
+                }
+                LibraryRequest::SavePlayQueue(song_ids, current, position) => {
+                    if let Err(err) =
+                        self.conn
+                            .save_play_queue(&song_ids, current.as_ref(), position)
+                    {
+                        let msg = format!("could not save play queue: {}", err);
+                        // the queue is also saved on exit, when the UI is gone
+                        if let Err(SendError(UiEvent::AddLog(msg))) =
+                            self.tx_ui_event.send(UiEvent::AddLog(msg))
+                        {
+                            self.unreported.push(msg);
+                        }
+                    }
+                }
+                // only the newest cover is shown, skip the ones scrolled past
+                LibraryRequest::GetCoverArt(_) if self.cover_superseded() => {}
+                LibraryRequest::GetCoverArt(id) => match self.get_cover_art(&id) {
+                    Ok(cover) => {
+                        self.tx_ui_event
+                            .send(UiEvent::LibraryCoverArtComplete(id, cover))?;
+                    }
+                    Err(err) => {
+                        self.tx_ui_event
+                            .send(UiEvent::LibraryCoverArtFailed(id, err.to_string()))?;
+                    }
+                },
+                LibraryRequest::GetArtistInfo(artist_id) => {
+                    match self.get_artist_info(&artist_id) {
+                        Ok(info) => {
+                            self.tx_ui_event
+                                .send(UiEvent::LibraryArtistInfoComplete(artist_id, info))?;
+                        }
+                        Err(err) => {
+                            self.tx_ui_event.send(UiEvent::LibraryArtistInfoFailed(
+                                artist_id,
+                                err.to_string(),
+                            ))?;
+                        }
+                    }
+                }
+                LibraryRequest::GetLyrics(id) => match self.get_lyrics(&id) {
+                    Ok(lyrics) => {
+                        self.tx_ui_event
+                            .send(UiEvent::LibraryLyricsComplete(id, lyrics))?;
+                    }
+                    Err(err) => {
+                        self.tx_ui_event
+                            .send(UiEvent::LibraryLyricsFailed(id, err.to_string()))?;
+                    }
+                },
+                LibraryRequest::DeletePlaylist(playlist_id) => {
+                    let result = self.conn.delete_playlist(&playlist_id).map(|_| ());
+                    self.playlist_changed(result, None)?;
+                    self.send_children(LibraryItemKey::Playlists)?;
+                }
+                LibraryRequest::Shutdown => {
+                    if let Err(err) = self.save_cache() {
+                        // the UI is gone at this point
+                        self.unreported
+                            .push(format!("could not save library cache: {}", err));
+                    }
+                    break;
+                }
+            }
+        }
+        Ok(std::mem::take(&mut self.unreported))
+    }
+
+    /// Check the connection to the server of the current profile and restore its cache.
+    fn connect(&mut self) -> Result<()> {
+        let status = match self.conn.connect() {
+            Ok(server) => {
+                for extension in Extension::ALL {
+                    let state = if self.conn.supports(extension) {
+                        "enabled"
+                    } else {
+                        "disabled"
+                    };
+                    self.tx_ui_event.send(UiEvent::AddLog(format!(
+                        "{}: {}",
+                        extension.name(),
+                        state
+                    )))?;
+                }
+                format!(
+                    "[{}] connected to: {} ({})",
+                    self.profile.name, self.profile.url, server
+                )
+            }
+            Err(err) => {
+                self.tx_ui_event
+                    .send(UiEvent::AddLog(format!("connect: {}", err)))?;
+                format!(
+                    "[{}] not connected: {}",
+                    self.profile.name, self.profile.url
+                )
+            }
+        };
+        self.tx_ui_event.send(UiEvent::SetStatus(status))?;
+        // the other threads need to know about the capabilities of the server as well
+        self.tx_ui_event
+            .send(UiEvent::LibraryConnected(self.conn.clone()))?;
+        self.restore_state()?;
+        self.restore_cache()?;
+        self.restore_play_queue()
+    }
+
+    /// Send the play queue stored on the server to the UI. It is not sent if the server
+    /// cannot be reached, so that the UI does not overwrite it.
+    fn restore_play_queue(&mut self) -> Result<()> {
+        let play_queue = match self.conn.get_play_queue() {
+            Ok(resp) => match resp.data {
+                Some(SubsonicData::PlayQueue(play_queue)) => Some(play_queue),
+                _ => None,
+            },
+            // some servers answer with an error if no queue has been saved
+            Err(err) if err.downcast_ref::<ConnectionError>().is_some() => None,
+            Err(err) => {
+                self.tx_ui_event.send(UiEvent::AddLog(format!(
+                    "could not restore play queue: {}",
+                    err
+                )))?;
+                return Ok(());
+            }
+        };
+        let saved_queue = play_queue.map(|play_queue| {
+            let current = play_queue
+                .current
+                .as_ref()
+                .and_then(|current| play_queue.entry.iter().position(|song| song.id == *current));
+            SavedQueue {
+                entries: play_queue
+                    .entry
+                    .iter()
+                    .map(|song| QueueEntry {
+                        id: song.id.clone(),
+                        song: Song::from(song),
+                    })
+                    .collect(),
+                current,
+                position: Duration::from_millis(play_queue.position.unwrap_or_default()),
+                changed_by: play_queue.changed_by,
+                changed: play_queue.changed,
+            }
+        });
+        self.tx_ui_event
+            .send(UiEvent::LibraryPlayQueueComplete(saved_queue))?;
+        Ok(())
+    }
+
+    /// Load the persisted state for the server of the current profile.
+    fn restore_state(&mut self) -> Result<()> {
+        self.state = LibraryState::default();
+        self.state_file = state_file(&self.profile.url, &self.profile.user);
+        if let Some(state_file) = self.state_file.as_ref().filter(|path| path.exists()) {
+            match LibraryState::load(state_file) {
+                Ok(state) => self.state = state,
+                Err(err) => {
+                    self.tx_ui_event.send(UiEvent::AddLog(format!(
+                        "discarding library state: {}",
+                        err
+                    )))?;
+                }
+            }
+        }
+        self.cache_file = cache_file(
+            &self.profile.url,
+            &self.profile.user,
+            self.state.music_folder.as_ref(),
+        );
+        Ok(())
+    }
+
+    fn get_music_folders(&self) -> Result<Vec<(Id, String)>> {
+        match self.conn.get_music_folders()?.data {
+            Some(SubsonicData::MusicFolders(music_folders)) => Ok(music_folders
+                .music_folder
+                .into_iter()
+                .map(|music_folder| {
+                    let name = music_folder.name.unwrap_or_else(|| music_folder.id.clone());
+                    (music_folder.id, name)
+                })
+                .collect()),
+            _ => Ok(vec![]),
+        }
+    }
+
+    /// Restrict the library to a music folder, or lift the restriction with `None`. Each
+    /// folder has its own cache.
+    fn set_music_folder(&mut self, music_folder: Option<Id>) -> Result<()> {
+        if let Err(err) = self.save_cache() {
+            self.tx_ui_event.send(UiEvent::AddLog(format!(
+                "could not save library cache: {}",
+                err
+            )))?;
+        }
+        self.state.music_folder = music_folder;
+        if let Some(state_file) = &self.state_file {
+            if let Err(err) = self.state.save(state_file) {
+                self.tx_ui_event.send(UiEvent::AddLog(format!(
+                    "could not save library state: {}",
+                    err
+                )))?;
+            }
+        }
+        self.cache = LibraryCache::new();
+        self.cache_file = cache_file(
+            &self.profile.url,
+            &self.profile.user,
+            self.state.music_folder.as_ref(),
+        );
+        self.restore_cache()
+    }
+
+    /// Load the persisted cache and drop the parts which have changed on the server since.
+    fn restore_cache(&mut self) -> Result<()> {
+        if let Some(cache_file) = self.cache_file.as_ref().filter(|path| path.exists()) {
+            match LibraryCache::load(cache_file) {
+                Ok(cache) => self.cache = cache,
+                Err(err) => {
+                    self.tx_ui_event.send(UiEvent::AddLog(format!(
+                        "discarding library cache: {}",
+                        err
+                    )))?;
+                }
+            }
+        }
+
+        match self
+            .conn
+            .get_indexes(self.cache.last_modified(), self.state.music_folder.as_ref())
+        {
+            Ok(resp) => {
+                if self.cache.update_indexes(resp) {
                     self.tx_ui_event
-                        .send(UiEvent::LibraryGetChildrenComplete(key, children))?;
+                        .send(UiEvent::AddLog(String::from("library changed on server")))?;
                 }
-                LibraryRequest::FindEntries(_, _, _) => todo!(),
-                LibraryRequest::Shutdown => break,
+            }
+            Err(err) => {
+                self.tx_ui_event.send(UiEvent::AddLog(format!(
+                    "could not check library for changes: {}",
+                    err
+                )))?;
+            }
+        }
+        Ok(())
+    }
+
+    fn save_cache(&self) -> Result<()> {
+        match &self.cache_file {
+            Some(cache_file) => self.cache.save(cache_file),
+            None => Ok(()),
+        }
+    }
+
+    fn send_children(&mut self, key: LibraryItemKey) -> Result<()> {
+        match self.get_children(&key) {
+            Ok(children) => {
+                let node = LibraryNode {
+                    children,
+                    this: self.cache.get_item(&key),
+                    parent: self.cache.get_parent(&key),
+                    more: self.cache.has_more(&key),
+                };
+                self.tx_ui_event
+                    .send(UiEvent::LibraryGetChildrenComplete(key, node))?;
+            }
+            Err(err) => {
+                self.tx_ui_event
+                    .send(UiEvent::LibraryRequestFailed(key, err.to_string()))?;
             }
         }
         Ok(())
     }
 
     fn get_children(&mut self, key: &LibraryItemKey) -> Result<Vec<(LibraryItemKey, LibraryItem)>> {
+        match key {
+            // the virtual nodes come first, the artists are sorted by the UI. The nodes do not
+            // depend on the artists, they are listed even if those cannot be fetched.
+            LibraryItemKey::Root => {
+                let mut children = root_nodes();
+                match self.fetch_children(key) {
+                    Ok(artists) => children.extend(artists),
+                    Err(err) => {
+                        self.tx_ui_event
+                            .send(UiEvent::AddLog(format!("could not get artists: {}", err)))?;
+                    }
+                }
+                Ok(children)
+            }
+            LibraryItemKey::Decades => Ok(decade_nodes(Utc::now().year() as u32)),
+            LibraryItemKey::Genre(genre) => Ok(genre_nodes(genre)),
+            _ => self.fetch_children(key),
+        }
+    }
+
+    /// Get the children of an item from the cache, fetching them from the server if needed.
+    fn fetch_children(
+        &mut self,
+        key: &LibraryItemKey,
+    ) -> Result<Vec<(LibraryItemKey, LibraryItem)>> {
         if let Some(children) = self.cache.get_children(key) {
             Ok(children)
         } else {
             match key {
                 LibraryItemKey::Root => {
-                    let resp = self.conn.get_artists()?;
+                    let resp = self.conn.get_artists(self.state.music_folder.as_ref())?;
                     self.cache.update_root(resp);
                 }
                 LibraryItemKey::Artist(artist_id) => {
@@ -62,72 +514,726 @@ impl Library {
                     let resp = self.conn.get_album(album_id)?;
                     self.cache.update_album(resp, album_id);
                 }
-                LibraryItemKey::Song(_) => {}
+                LibraryItemKey::Genres => {
+                    let resp = self.conn.get_genres()?;
+                    self.cache.update_genres(resp);
+                }
+                LibraryItemKey::Starred => {
+                    let resp = self.conn.get_starred2(self.state.music_folder.as_ref())?;
+                    self.cache.update_starred(resp);
+                }
+                LibraryItemKey::RandomSongs => {
+                    let resp = self
+                        .conn
+                        .get_random_songs(PAGE_SIZE, self.state.music_folder.as_ref())?;
+                    self.cache.update_random_songs(resp);
+                }
+                LibraryItemKey::Playlists => {
+                    let resp = self.conn.get_playlists()?;
+                    self.cache.update_playlists(resp);
+                }
+                LibraryItemKey::Playlist(playlist_id) => {
+                    let resp = self.conn.get_playlist(playlist_id)?;
+                    self.cache.update_playlist(resp);
+                }
+                LibraryItemKey::AlbumList(_) | LibraryItemKey::GenreSongs(_) => {
+                    self.fetch_page(key)?
+                }
+                LibraryItemKey::Decades | LibraryItemKey::Genre(_) | LibraryItemKey::Song(_) => {}
             }
-            if let Some(children) = self.cache.get_children(key) {
-                Ok(children)
+            // the cache does not distinguish empty items from unfetched ones
+            Ok(self.cache.get_children(key).unwrap_or_default())
+        }
+    }
+
+    /// Fetch the next page of a paged item.
+    fn fetch_page(&mut self, key: &LibraryItemKey) -> Result<()> {
+        let offset = self.cache.list_len(key);
+        let music_folder = self.state.music_folder.as_ref();
+        match key {
+            LibraryItemKey::AlbumList(list_type) => {
+                let resp = self
+                    .conn
+                    .get_album_list2(list_type, PAGE_SIZE, offset, music_folder)?;
+                self.cache.update_album_list(resp, list_type, PAGE_SIZE);
+            }
+            LibraryItemKey::GenreSongs(genre) => {
+                let resp = self
+                    .conn
+                    .get_songs_by_genre(genre, PAGE_SIZE, offset, music_folder)?;
+                self.cache.update_genre_songs(resp, genre, PAGE_SIZE);
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    /// Change the marks of an item. The change is shown right away and undone if the server
+    /// rejects it.
+    fn update_marks(&mut self, key: LibraryItemKey, change: impl FnOnce(&mut Marks)) -> Result<()> {
+        let previous = match self.cache.get_marks(&key) {
+            Some(marks) => marks,
+            None => return Ok(()),
+        };
+        let mut marks = previous;
+        change(&mut marks);
+        self.cache.set_marks(&key, marks);
+        self.send_item_changed(&key)?;
+
+        if let Err(err) = self.send_marks(&key, previous, marks) {
+            self.cache.set_marks(&key, previous);
+            self.send_item_changed(&key)?;
+            self.tx_ui_event.send(UiEvent::AddLog(format!(
+                "could not update star or rating: {}",
+                err
+            )))?;
+        }
+        Ok(())
+    }
+
+    fn send_marks(&self, key: &LibraryItemKey, previous: Marks, marks: Marks) -> Result<()> {
+        let (id, target) = match key {
+            LibraryItemKey::Artist(id) => (id, StarTarget::Artist(id)),
+            LibraryItemKey::Album(id) => (id, StarTarget::Album(id)),
+            LibraryItemKey::Song(id) => (id, StarTarget::Song(id)),
+            _ => return Ok(()),
+        };
+        if marks.starred != previous.starred {
+            if marks.starred {
+                self.conn.star(target)?;
             } else {
-                bail!("empty children")
+                self.conn.unstar(target)?;
             }
         }
+        if marks.rating != previous.rating {
+            self.conn.set_rating(id, marks.rating)?;
+        }
+        Ok(())
+    }
+
+    fn send_item_changed(&self, key: &LibraryItemKey) -> Result<()> {
+        if let Some(item) = self.cache.get_item(key) {
+            self.tx_ui_event
+                .send(UiEvent::LibraryItemChanged(key.clone(), item))?;
+        }
+        Ok(())
+    }
+
+    /// Add the songs of an item to a playlist, or to a new playlist named after the item.
+    fn add_to_playlist(&mut self, playlist_id: Option<Id>, key: &LibraryItemKey) -> Result<()> {
+        let song_ids: Vec<_> = self
+            .get_songs(key)?
+            .into_iter()
+            .filter_map(|(key, _)| match key {
+                LibraryItemKey::Song(id) => Some(id),
+                _ => None,
+            })
+            .collect();
+        let message = match playlist_id {
+            Some(playlist_id) => {
+                self.conn
+                    .update_playlist(&playlist_id, None, &song_ids, &[])?;
+                self.cache
+                    .invalidate(&LibraryItemKey::Playlist(playlist_id.clone()));
+                let name = self
+                    .cache
+                    .get_item(&LibraryItemKey::Playlist(playlist_id))
+                    .map(|item| item.name())
+                    .unwrap_or_default();
+                format!("added {} songs to playlist {}", song_ids.len(), name)
+            }
+            None => {
+                let name = self
+                    .cache
+                    .get_item(key)
+                    .map(|item| item.name())
+                    .unwrap_or_default();
+                self.conn.create_playlist(&name, &song_ids)?;
+                format!("created playlist {} with {} songs", name, song_ids.len())
+            }
+        };
+        self.tx_ui_event.send(UiEvent::AddLog(message))?;
+        Ok(())
+    }
+
+    /// Move a song of a playlist by replacing all of its songs, as `updatePlaylist` can only
+    /// add songs at the end.
+    fn move_playlist_entry(&mut self, playlist_id: &Id, from: usize, to: usize) -> Result<()> {
+        let mut song_ids = match self.cache.get_playlist_songs(playlist_id) {
+            Some(song_ids) => song_ids.to_vec(),
+            None => return Ok(()),
+        };
+        if from >= song_ids.len() || to >= song_ids.len() {
+            return Ok(());
+        }
+        let song_id = song_ids.remove(from);
+        song_ids.insert(to, song_id);
+        self.conn.set_playlist_songs(playlist_id, &song_ids)?;
+        Ok(())
+    }
+
+    /// Drop the cached playlists after a change and show the changed playlist again, which is
+    /// the one currently viewed.
+    fn playlist_changed(&mut self, result: Result<()>, playlist_id: Option<Id>) -> Result<()> {
+        if let Err(err) = result {
+            self.tx_ui_event.send(UiEvent::AddLog(format!(
+                "could not change playlist: {}",
+                err
+            )))?;
+        }
+        self.cache.invalidate(&LibraryItemKey::Playlists);
+        if let Some(playlist_id) = playlist_id {
+            let key = LibraryItemKey::Playlist(playlist_id);
+            self.cache.invalidate(&key);
+            self.send_children(key)?;
+        }
+        Ok(())
+    }
+
+    /// Get cover art from the disk cache, fetching it from the server if needed.
+    fn get_cover_art(&self, id: &Id) -> Result<Cover> {
+        let file = cover_file(&self.profile.url, id);
+        if let Some(data) = file.as_ref().and_then(|file| fs::read(file).ok()) {
+            // mark the cover as recently used
+            if let Some(file) = &file {
+                let _ = fs::File::options()
+                    .write(true)
+                    .open(file)
+                    .and_then(|file| file.set_modified(SystemTime::now()));
+            }
+            return Cover::decode(&data);
+        }
+        let mut data = vec![];
+        self.conn
+            .get_cover_art(id, Some(COVER_SIZE))?
+            .read_to_end(&mut data)?;
+        let cover = Cover::decode(&data)?;
+        if let Some(file) = file {
+            if let Some(dir) = file.parent() {
+                fs::create_dir_all(dir)?;
+                fs::write(&file, data)?;
+                prune_covers(dir, COVER_CACHE_SIZE)?;
+            }
+        }
+        Ok(cover)
+    }
+
+    /// The biography, similar artists and top songs of an artist. Top songs are looked up by
+    /// the name of the artist, which is known once its albums have been fetched. If only one of
+    /// the requests fails, the panel shows the results of the other one.
+    fn get_artist_info(&mut self, artist_id: &Id) -> Result<ArtistInfo> {
+        if let Some(info) = self.cache.get_artist_info(artist_id) {
+            return Ok(info);
+        }
+        let info_resp = self.conn.get_artist_info2(artist_id, ARTIST_INFO_COUNT);
+        let top_songs_resp = match self
+            .cache
+            .get_item(&LibraryItemKey::Artist(artist_id.clone()))
+        {
+            Some(LibraryItem::Artist(artist)) => {
+                match self
+                    .conn
+                    .get_top_songs(&artist.name.to_string(), ARTIST_INFO_COUNT)
+                {
+                    Ok(resp) => Some(resp),
+                    Err(err) => {
+                        self.tx_ui_event
+                            .send(UiEvent::AddLog(format!("top songs failed: {}", err)))?;
+                        None
+                    }
+                }
+            }
+            _ => None,
+        };
+        // without any results, report the failure instead of an empty panel
+        let info_resp = match (info_resp, &top_songs_resp) {
+            (Ok(resp), _) => Some(resp),
+            (Err(err), None) => return Err(err),
+            (Err(err), Some(_)) => {
+                self.tx_ui_event
+                    .send(UiEvent::AddLog(format!("artist info failed: {}", err)))?;
+                None
+            }
+        };
+        self.cache
+            .update_artist_info(artist_id, info_resp, top_songs_resp);
+        Ok(self.cache.get_artist_info(artist_id).unwrap_or_default())
+    }
+
+    /// Lyrics of a song, from `getLyricsBySongId` if the server supports it, else from
+    /// `getLyrics` with the artist and title of the song.
+    fn get_lyrics(&self, id: &Id) -> Result<Option<Lyrics>> {
+        if self.conn.supports(Extension::SongLyrics) {
+            if let Some(SubsonicData::LyricsList(list)) = self.conn.get_lyrics_by_song_id(id)?.data
+            {
+                if let Some(lyrics) = Lyrics::from_structured(list.structured_lyrics) {
+                    return Ok(Some(lyrics));
+                }
+            }
+        }
+        let Some(SubsonicData::Song(song)) = self.conn.get_song(id)?.data else {
+            return Ok(None);
+        };
+        let Some(artist) = song.artist else {
+            return Ok(None);
+        };
+        match self.conn.get_lyrics(&artist, &song.title)?.data {
+            Some(SubsonicData::Lyrics(lyrics)) => Ok(Lyrics::from_text(&lyrics.value)),
+            _ => Ok(None),
+        }
+    }
+
+    /// Search the cache and the server. Results from the cache are sent immediately, followed
+    /// by the combined results once the server has answered. Searches for which a newer search
+    /// request is already waiting are dropped.
+    fn find_entries(&mut self, request_id: usize, find_type: FindType, query: &str) -> Result<()> {
+        if self.search_superseded() {
+            return Ok(());
+        }
+
+        let search_string = SearchString::from(query);
+        if query.is_empty() {
+            self.tx_ui_event
+                .send(UiEvent::LibraryFindEntriesComplete(request_id, vec![]))?;
+            return Ok(());
+        }
+        self.tx_ui_event.send(UiEvent::LibraryFindEntriesComplete(
+            request_id,
+            self.cache.find(find_type, &search_string),
+        ))?;
+
+        let count = |t| {
+            if find_type.includes(t) {
+                SEARCH_COUNT
+            } else {
+                0
+            }
+        };
+        let resp = match self.conn.search3(
+            query,
+            count(FindType::Artist),
+            count(FindType::Album),
+            count(FindType::Song),
+            self.state.music_folder.as_ref(),
+        ) {
+            Ok(resp) => resp,
+            Err(err) => {
+                self.tx_ui_event.send(UiEvent::LibraryFindEntriesFailed(
+                    request_id,
+                    err.to_string(),
+                ))?;
+                return Ok(());
+            }
+        };
+        self.cache.update_search(resp);
+
+        if !self.search_superseded() {
+            self.tx_ui_event.send(UiEvent::LibraryFindEntriesComplete(
+                request_id,
+                self.cache.find(find_type, &search_string),
+            ))?;
+        }
+        Ok(())
+    }
+
+    /// Check if a newer search request is waiting in the request queue.
+    fn search_superseded(&mut self) -> bool {
+        self.pending.extend(self.rx_request.try_iter());
+        self.pending
+            .iter()
+            .any(|request| matches!(request, LibraryRequest::FindEntries(..)))
+    }
+
+    /// Check if a newer cover art request is waiting in the request queue.
+    fn cover_superseded(&mut self) -> bool {
+        self.pending.extend(self.rx_request.try_iter());
+        self.pending
+            .iter()
+            .any(|request| matches!(request, LibraryRequest::GetCoverArt(_)))
+    }
+
+    /// Get all songs below the given item, in album / track order.
+    fn get_songs(&mut self, key: &LibraryItemKey) -> Result<Vec<(LibraryItemKey, LibraryItem)>> {
+        match key {
+            LibraryItemKey::Root
+            | LibraryItemKey::Decades
+            | LibraryItemKey::Genres
+            | LibraryItemKey::Genre(_)
+            | LibraryItemKey::Playlists => Ok(vec![]),
+            // only the songs loaded so far
+            LibraryItemKey::GenreSongs(_) => self.get_children(key),
+            LibraryItemKey::Starred => Ok(self
+                .get_children(key)?
+                .into_iter()
+                .filter(|(key, _)| matches!(key, LibraryItemKey::Song(_)))
+                .collect()),
+            // only the albums loaded so far
+            LibraryItemKey::Artist(_) | LibraryItemKey::AlbumList(_) => {
+                let mut songs = vec![];
+                for (album_key, _) in self.get_children(key)? {
+                    songs.extend(self.get_songs(&album_key)?);
+                }
+                Ok(songs)
+            }
+            LibraryItemKey::Album(_)
+            | LibraryItemKey::Playlist(_)
+            | LibraryItemKey::RandomSongs => self.get_children(key),
+            LibraryItemKey::Song(song_id) => Ok(self
+                .cache
+                .get_song(song_id)
+                .map(|song| (key.clone(), LibraryItem::Song(song)))
+                .into_iter()
+                .collect()),
+        }
     }
 }
 
-#[derive(PartialEq, Eq, Hash, Clone)]
+/// Location of the persisted library cache for a server, user and music folder, in the XDG
+/// cache directory.
+fn cache_file(url: &str, user: &str, music_folder: Option<&Id>) -> Option<PathBuf> {
+    let key = match music_folder {
+        Some(music_folder) => format!("{}\n{}\n{}", url, user, music_folder),
+        None => format!("{}\n{}", url, user),
+    };
+    let key = md5::Md5::digest(key);
+    dirs::cache_dir().map(|dir| dir.join("navicon").join(format!("{:032x}.json", key)))
+}
+
+/// Location of the cached cover art of a server, in the XDG cache directory.
+fn cover_file(url: &str, id: &Id) -> Option<PathBuf> {
+    let key = md5::Md5::digest(format!("{}\n{}\n{}", url, id, COVER_SIZE));
+    dirs::cache_dir().map(|dir| {
+        dir.join("navicon")
+            .join("covers")
+            .join(format!("{:032x}", key))
+    })
+}
+
+/// Remove the least recently used covers from the cover cache until it fits into `max_size`
+/// bytes.
+fn prune_covers(dir: &Path, max_size: u64) -> Result<()> {
+    let mut files = vec![];
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let metadata = entry.metadata()?;
+        if metadata.is_file() {
+            files.push((metadata.modified()?, metadata.len(), entry.path()));
+        }
+    }
+    let mut size: u64 = files.iter().map(|(_, len, _)| len).sum();
+    files.sort();
+    for (_, len, path) in files {
+        if size <= max_size {
+            break;
+        }
+        fs::remove_file(path)?;
+        size -= len;
+    }
+    Ok(())
+}
+
+/// Location of the persisted library state for a server and user, in the XDG data directory.
+fn state_file(url: &str, user: &str) -> Option<PathBuf> {
+    let key = md5::Md5::digest(format!("{}\n{}", url, user));
+    dirs::data_dir().map(|dir| dir.join("navicon").join(format!("{:032x}.json", key)))
+}
+
+/// The virtual nodes listed above the artists at the root of the library.
+fn root_nodes() -> Vec<(LibraryItemKey, LibraryItem)> {
+    let mut nodes = vec![
+        (
+            LibraryItemKey::Starred,
+            LibraryItem::Node(STARRED_TITLE.to_string()),
+        ),
+        (
+            LibraryItemKey::Playlists,
+            LibraryItem::Node(PLAYLISTS_TITLE.to_string()),
+        ),
+    ];
+    nodes.extend(
+        [
+            AlbumListType::Newest,
+            AlbumListType::Recent,
+            AlbumListType::Frequent,
+            AlbumListType::Highest,
+            AlbumListType::Random,
+            AlbumListType::AlphabeticalByName,
+            AlbumListType::AlphabeticalByArtist,
+        ]
+        .into_iter()
+        .map(|list_type| {
+            let item = LibraryItem::Node(list_type.to_string());
+            (LibraryItemKey::AlbumList(list_type), item)
+        }),
+    );
+    nodes.push((
+        LibraryItemKey::RandomSongs,
+        LibraryItem::Node(RANDOM_SONGS_TITLE.to_string()),
+    ));
+    nodes.push((
+        LibraryItemKey::Decades,
+        LibraryItem::Node(DECADES_TITLE.to_string()),
+    ));
+    nodes.push((
+        LibraryItemKey::Genres,
+        LibraryItem::Node(GENRES_TITLE.to_string()),
+    ));
+    nodes
+}
+
+/// The albums and the songs of a genre.
+fn genre_nodes(genre: &str) -> Vec<(LibraryItemKey, LibraryItem)> {
+    let list_type = AlbumListType::ByGenre(genre.to_string());
+    vec![
+        (
+            LibraryItemKey::AlbumList(list_type.clone()),
+            LibraryItem::Node(list_type.to_string()),
+        ),
+        (
+            LibraryItemKey::GenreSongs(genre.to_string()),
+            LibraryItem::Node(format!("{} songs", genre)),
+        ),
+    ]
+}
+
+/// Album lists for the decades up to the one containing `year`, latest first.
+fn decade_nodes(year: u32) -> Vec<(LibraryItemKey, LibraryItem)> {
+    (0..=year.saturating_sub(FIRST_DECADE) / 10)
+        .rev()
+        .map(|decade| {
+            let from = FIRST_DECADE + decade * 10;
+            let list_type = AlbumListType::ByYear(from, from + 9);
+            let item = LibraryItem::Node(list_type.to_string());
+            (LibraryItemKey::AlbumList(list_type), item)
+        })
+        .collect()
+}
+
+#[derive(PartialEq, Eq, Hash, Clone, Debug, Serialize, Deserialize)]
 pub enum LibraryItemKey {
     Root,
+    /// a list of albums, loaded page by page
+    AlbumList(AlbumListType),
+    /// the album lists by decade
+    Decades,
+    Genres,
+    /// the starred artists, albums and songs
+    Starred,
+    /// songs picked at random by the server
+    RandomSongs,
+    Playlists,
+    Playlist(Id),
+    /// the albums and the songs of the genre
+    Genre(String),
+    /// the songs of the genre, loaded page by page
+    GenreSongs(String),
     Artist(Id),
     Album(Id),
     Song(Id),
 }
 
+#[derive(Clone)]
 pub enum LibraryItem {
+    /// a virtual node grouping other items, with its title
+    Node(String),
+    Genre(Genre),
+    Playlist(Playlist),
     Artist(Artist),
     Album(Album),
     Song(Song),
 }
 
+impl LibraryItem {
+    /// The name of the item, without any details.
+    pub fn name(&self) -> String {
+        match self {
+            LibraryItem::Node(title) => title.clone(),
+            LibraryItem::Genre(genre) => genre.name.clone(),
+            LibraryItem::Playlist(playlist) => playlist.name.clone(),
+            LibraryItem::Artist(artist) => artist.name.to_string(),
+            LibraryItem::Album(album) => album.name.to_string(),
+            LibraryItem::Song(song) => song.title.to_string(),
+        }
+    }
+}
+
 impl std::fmt::Display for LibraryItem {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
-            LibraryItem::Artist(artist) => write!(f, "{}", artist.name),
-            LibraryItem::Album(album) => write!(f, "{}", album.name),
+            LibraryItem::Node(title) => write!(f, "{}/", title),
+            LibraryItem::Genre(genre) => write!(
+                f,
+                "{} [{} albums, {} songs]",
+                genre.name, genre.album_count, genre.song_count
+            ),
+            LibraryItem::Playlist(playlist) => {
+                write!(
+                    f,
+                    "{} [{} songs, {}:{:02}]",
+                    playlist.name,
+                    playlist.song_count,
+                    playlist.duration / 60,
+                    playlist.duration % 60
+                )?;
+                match &playlist.owner {
+                    Some(owner) => write!(f, " by {}", owner),
+                    None => Ok(()),
+                }
+            }
+            LibraryItem::Artist(artist) => write!(f, "{}{}", artist.name, artist.marks),
+            LibraryItem::Album(album) => write!(f, "{}{}", album.name, album.marks),
             LibraryItem::Song(song) => write!(f, "{}", song),
         }
     }
 }
 
 #[derive(Clone)]
+pub struct Genre {
+    pub name: String,
+    pub album_count: usize,
+    pub song_count: usize,
+}
+
+#[derive(Clone)]
+pub struct Playlist {
+    pub name: String,
+    pub owner: Option<String>,
+    pub song_count: usize,
+    pub duration: usize,
+    pub changed: DateTime<Utc>,
+}
+
+impl From<&subsonic::Playlist> for Playlist {
+    fn from(playlist: &subsonic::Playlist) -> Self {
+        Playlist {
+            name: playlist.name.clone(),
+            owner: playlist.owner.clone(),
+            song_count: playlist.song_count,
+            duration: playlist.duration,
+            changed: playlist.changed,
+        }
+    }
+}
+
+/// Whether the user has starred an artist, album or song, and its rating.
+#[derive(Clone, Copy, Default, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub struct Marks {
+    pub starred: bool,
+    /// 1 to 5 stars, 0 if not rated
+    pub rating: u8,
+}
+
+impl Marks {
+    fn new(starred: &Option<DateTime<Utc>>, rating: Option<u8>) -> Marks {
+        Marks {
+            starred: starred.is_some(),
+            rating: rating.unwrap_or_default(),
+        }
+    }
+}
+
+impl std::fmt::Display for Marks {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.starred {
+            write!(f, " ♥")?;
+        }
+        if self.rating > 0 {
+            write!(f, " {}", "★".repeat(self.rating.into()))?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize)]
 pub struct Artist {
     pub name: SearchString,
+    pub marks: Marks,
 }
 
-#[derive(Clone)]
+impl From<&subsonic::Artist> for Artist {
+    fn from(artist: &subsonic::Artist) -> Self {
+        Artist {
+            name: artist.name.as_str().into(),
+            marks: Marks::new(&artist.starred, artist.user_rating),
+        }
+    }
+}
+
+/// Information on an artist from Last.fm, as provided by the server.
+#[derive(Clone, Default)]
+pub struct ArtistInfo {
+    pub biography: Option<String>,
+    pub music_brainz_id: Option<String>,
+    pub similar: Vec<(LibraryItemKey, LibraryItem)>,
+    pub top_songs: Vec<(LibraryItemKey, LibraryItem)>,
+}
+
+impl ArtistInfo {
+    /// The similar artists followed by the top songs.
+    pub fn entries(&self) -> impl Iterator<Item = &(LibraryItemKey, LibraryItem)> {
+        self.similar.iter().chain(&self.top_songs)
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize)]
 pub struct Album {
     pub name: SearchString,
+    pub created: DateTime<Utc>,
+    pub genre: Option<String>,
+    pub cover_art: Option<Id>,
+    pub marks: Marks,
 }
 
-#[derive(Clone)]
+impl From<&subsonic::Album> for Album {
+    fn from(album: &subsonic::Album) -> Self {
+        Album {
+            name: album.name.as_str().into(),
+            created: album.created,
+            genre: album.genre.clone(),
+            cover_art: album.cover_art.clone(),
+            marks: Marks::new(&album.starred, album.user_rating),
+        }
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize)]
 pub struct Song {
     pub title: SearchString,
     pub track_number: Option<usize>,
     pub duration: Option<usize>,
+    pub genre: Option<String>,
+    pub cover_art: Option<Id>,
+    pub marks: Marks,
+}
+
+impl From<&subsonic::Child> for Song {
+    fn from(song: &subsonic::Child) -> Self {
+        Song {
+            title: song.title.as_str().into(),
+            track_number: song.track,
+            duration: song.duration,
+            genre: song.genre.clone(),
+            cover_art: song.cover_art.clone(),
+            marks: Marks::new(&song.starred, song.user_rating),
+        }
+    }
 }
 
 impl std::fmt::Display for Song {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(
             f,
-            "{}: {} [{}:{:02}]",
+            "{}: {} [{}:{:02}]{}",
             self.track_number.unwrap_or_default(),
             self.title,
             self.duration.unwrap_or_default() / 60,
-            self.duration.unwrap_or_default() % 60
+            self.duration.unwrap_or_default() % 60,
+            self.marks
         )
     }
 }
 
-#[derive(Clone)]
+#[derive(Clone, Serialize, Deserialize)]
 pub struct SearchString {
     display: String,
     search: String,
@@ -153,3 +1259,39 @@ impl std::fmt::Display for SearchString {
         write!(f, "{}", self.display)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        env, fs,
+        time::{Duration, SystemTime},
+    };
+
+    use super::prune_covers;
+
+    #[test]
+    fn prune_least_recently_used_covers() {
+        let dir = env::temp_dir().join(format!("navicon-covers-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let now = SystemTime::now();
+        for (name, age) in [("old", 30), ("older", 60), ("new", 0)] {
+            let path = dir.join(name);
+            fs::write(&path, [0; 10]).unwrap();
+            fs::File::options()
+                .write(true)
+                .open(&path)
+                .unwrap()
+                .set_modified(now - Duration::from_secs(age))
+                .unwrap();
+        }
+
+        prune_covers(&dir, 20).unwrap();
+        let mut names: Vec<_> = fs::read_dir(&dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name())
+            .collect();
+        names.sort();
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(names, ["new", "old"]);
+    }
+}
@@ -1,14 +1,73 @@
+use std::time::Duration;
+
+use crate::{conn::Connection, queue::EnqueueMode, settings::Profile, subsonic::Id};
+
 use super::LibraryItemKey;
 
 pub enum LibraryRequest {
     GetChildren(LibraryItemKey),
+    /// drop the cached children of the item and fetch them again
+    Refresh(LibraryItemKey),
+    /// fetch the next page of a paged item, e.g. an album list
+    GetMore(LibraryItemKey),
     FindEntries(usize, FindType, String),
+    GetSongs(LibraryItemKey, EnqueueMode),
+    ToggleStar(LibraryItemKey),
+    /// rate an artist, album or song with 1 to 5 stars, 0 removes the rating
+    SetRating(LibraryItemKey, u8),
+    /// connect to the server of another profile, replacing the cache
+    SwitchProfile(Profile, Connection),
+    GetMusicFolders,
+    /// restrict the library to a music folder, or to none
+    SetMusicFolder(Option<Id>),
+    GetPlaylists,
+    /// add the songs of an item to a playlist, or to a new playlist named after the item
+    AddToPlaylist(Option<Id>, LibraryItemKey),
+    /// remove the song at the position from a playlist
+    RemoveFromPlaylist(Id, usize),
+    /// move the song of a playlist from the first position to the second
+    MovePlaylistEntry(Id, usize, usize),
+    DeletePlaylist(Id),
+    /// store the play queue on the server, with the current song and the position in it
+    SavePlayQueue(Vec<Id>, Option<Id>, Duration),
+    GetCoverArt(Id),
+    /// fetch the biography, similar artists and top songs of an artist
+    GetArtistInfo(Id),
+    /// fetch the lyrics of a song
+    GetLyrics(Id),
     Shutdown,
 }
 
+#[derive(Clone, Copy, PartialEq, Eq)]
 pub enum FindType {
     Any,
     Artist,
     Album,
     Song,
 }
+
+impl FindType {
+    pub fn includes(&self, other: FindType) -> bool {
+        *self == FindType::Any || *self == other
+    }
+
+    pub fn next(self) -> FindType {
+        match self {
+            FindType::Any => FindType::Artist,
+            FindType::Artist => FindType::Album,
+            FindType::Album => FindType::Song,
+            FindType::Song => FindType::Any,
+        }
+    }
+}
+
+impl std::fmt::Display for FindType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FindType::Any => write!(f, "all"),
+            FindType::Artist => write!(f, "artists"),
+            FindType::Album => write!(f, "albums"),
+            FindType::Song => write!(f, "songs"),
+        }
+    }
+}
@@ -0,0 +1,56 @@
+use std::{
+    fs::{self, File},
+    io::{BufReader, BufWriter, Write},
+    path::Path,
+};
+
+use color_eyre::Result;
+use serde::{Deserialize, Serialize};
+
+use crate::subsonic::Id;
+
+/// Choices made in the library which are kept across sessions, per server and user.
+#[derive(Default, Serialize, Deserialize)]
+pub struct LibraryState {
+    /// the music folder the library is restricted to, all folders if `None`
+    pub music_folder: Option<Id>,
+}
+
+impl LibraryState {
+    pub fn load(path: &Path) -> Result<LibraryState> {
+        Ok(serde_json::from_reader(BufReader::new(File::open(path)?))?)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        // write to a temporary file first, so that an interrupted write keeps the old state
+        let tmp_path = path.with_extension("tmp");
+        let mut writer = BufWriter::new(File::create(&tmp_path)?);
+        serde_json::to_writer(&mut writer, self)?;
+        writer.flush()?;
+        fs::rename(tmp_path, path)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::LibraryState;
+
+    #[test]
+    fn save_load() {
+        let path = std::env::temp_dir()
+            .join(format!("navicon-state-test-{}", std::process::id()))
+            .join("state.json");
+        let state = LibraryState {
+            music_folder: Some("2".into()),
+        };
+        state.save(&path).unwrap();
+        let loaded = LibraryState::load(&path).unwrap();
+        std::fs::remove_dir_all(path.parent().unwrap()).unwrap();
+
+        assert_eq!(loaded.music_folder.as_deref(), Some("2"));
+    }
+}
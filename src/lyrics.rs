@@ -0,0 +1,142 @@
+use std::time::Duration;
+
+use crate::subsonic::StructuredLyrics;
+
+/// The lyrics of a song, either synced to the playback position or plain text.
+pub struct Lyrics {
+    pub lines: Vec<Line>,
+    pub synced: bool,
+}
+
+pub struct Line {
+    /// when the line is sung, zero for plain lyrics
+    pub start: Duration,
+    pub text: String,
+}
+
+impl Lyrics {
+    /// Pick lyrics from the ones returned by `getLyricsBySongId`, preferring synced lyrics.
+    /// The offset of the lyrics is applied to the start of the lines.
+    pub fn from_structured(list: Vec<StructuredLyrics>) -> Option<Lyrics> {
+        let lyrics = list
+            .into_iter()
+            .filter(|lyrics| !lyrics.line.is_empty())
+            // the first synced lyrics, else the first plain ones
+            .min_by_key(|lyrics| !lyrics.synced)?;
+        let offset = lyrics.offset.unwrap_or(0);
+        let lines = lyrics
+            .line
+            .into_iter()
+            .map(|line| {
+                let start = line.start.unwrap_or(0) as i64 - offset;
+                Line {
+                    start: Duration::from_millis(start.max(0) as u64),
+                    text: line.value,
+                }
+            })
+            .collect();
+        Some(Lyrics {
+            lines,
+            synced: lyrics.synced,
+        })
+    }
+
+    /// Plain lyrics from the text returned by `getLyrics`, `None` if it is empty.
+    pub fn from_text(text: &str) -> Option<Lyrics> {
+        let text = text.trim();
+        if text.is_empty() {
+            return None;
+        }
+        let lines = text
+            .lines()
+            .map(|line| Line {
+                start: Duration::ZERO,
+                text: line.trim_end().to_string(),
+            })
+            .collect();
+        Some(Lyrics {
+            lines,
+            synced: false,
+        })
+    }
+
+    /// Index of the line sung at the playback position, `None` for plain lyrics or before the
+    /// first line.
+    pub fn current_line(&self, position: Duration) -> Option<usize> {
+        if !self.synced {
+            return None;
+        }
+        self.lines
+            .partition_point(|line| line.start <= position)
+            .checked_sub(1)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use crate::subsonic::{LyricsLine, StructuredLyrics};
+
+    use super::Lyrics;
+
+    fn structured(synced: bool, offset: Option<i64>, lines: &[(u64, &str)]) -> StructuredLyrics {
+        StructuredLyrics {
+            display_artist: None,
+            display_title: None,
+            lang: "eng".into(),
+            offset,
+            synced,
+            line: lines
+                .iter()
+                .map(|(start, text)| LyricsLine {
+                    start: synced.then_some(*start),
+                    value: text.to_string(),
+                })
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn prefer_synced() {
+        let lyrics = Lyrics::from_structured(vec![
+            structured(false, None, &[(0, "plain")]),
+            structured(true, Some(-100), &[(0, "one"), (2500, "two")]),
+        ])
+        .unwrap();
+        assert!(lyrics.synced);
+        let starts: Vec<_> = lyrics.lines.iter().map(|line| line.start).collect();
+        assert_eq!(
+            starts,
+            [Duration::from_millis(100), Duration::from_millis(2600)]
+        );
+
+        let lyrics = Lyrics::from_structured(vec![
+            structured(true, None, &[]),
+            structured(false, None, &[(0, "plain")]),
+        ])
+        .unwrap();
+        assert!(!lyrics.synced);
+        assert!(Lyrics::from_structured(vec![]).is_none());
+    }
+
+    #[test]
+    fn current_line() {
+        let lyrics = Lyrics::from_structured(vec![structured(
+            true,
+            None,
+            &[(1000, "one"), (3000, "two")],
+        )])
+        .unwrap();
+        let current = |ms| lyrics.current_line(Duration::from_millis(ms));
+        assert_eq!(current(500), None);
+        assert_eq!(current(1000), Some(0));
+        assert_eq!(current(2999), Some(0));
+        assert_eq!(current(60000), Some(1));
+
+        let plain = Lyrics::from_text("one\ntwo\n").unwrap();
+        assert_eq!(plain.lines.len(), 2);
+        assert_eq!(plain.current_line(Duration::from_secs(10)), None);
+        assert!(Lyrics::from_text(" \n").is_none());
+    }
+}
@@ -1,47 +1,96 @@
 mod conn;
+mod cover;
 mod library;
+mod lyrics;
+mod player;
+mod queue;
+mod settings;
 mod subsonic;
 mod ui;
 
-use std::{sync::mpsc::channel, thread};
+use std::{path::PathBuf, sync::mpsc::channel, thread};
 
+use clap::Parser;
 use color_eyre::Result;
-use config::Config;
 
-use ui::event::UiEvent;
+use crate::{
+    cover::Graphics, library::Library, player::Player, settings::password::SystemKeyring, ui::Ui,
+};
 
-use crate::{library::Library, ui::Ui};
+/// A terminal-based Navidrome / Subsonic compatible audio player.
+#[derive(Parser)]
+#[command(version, about)]
+struct Args {
+    /// Configuration file [default: $XDG_CONFIG_HOME/navicon/config.toml]
+    #[arg(long)]
+    config: Option<PathBuf>,
+    /// Profile to connect to, as configured in `[profiles.<name>]`
+    #[arg(long)]
+    profile: Option<String>,
+}
 
 fn main() -> Result<()> {
     color_eyre::install()?;
 
+    let args = Args::parse();
+    let config_path = match args.config {
+        Some(path) => path,
+        None => settings::default_path()?,
+    };
+    // load the settings before the UI takes over the terminal, the setup is interactive
+    let settings::Settings {
+        profiles,
+        active,
+        output,
+        graphics,
+    } = settings::load_or_setup(&config_path, args.profile.as_deref())?;
+    let profile = profiles[active].clone();
+    let conn = profile.connection(&SystemKeyring)?;
+    let graphics = Graphics::select(graphics.as_deref())?;
+
     let (tx_library_request, rx_library_request) = channel();
+    let (tx_player_request, rx_player_request) = channel();
     let (tx_ui_event, rx_ui_event) = channel();
 
-    let ui_handler = thread::spawn(|| {
-        let mut ui = Ui::new(tx_library_request, rx_ui_event).unwrap();
+    let ui_handler = thread::spawn(move || {
+        let mut ui = Ui::new(
+            profiles,
+            active,
+            graphics,
+            tx_library_request,
+            tx_player_request,
+            rx_ui_event,
+        )
+        .unwrap();
         ui.run().unwrap();
     });
 
-    let config = Config::builder()
-        .add_source(config::File::with_name("settings"))
-        .build()?;
-
-    let url: String = config.get("url")?;
-    let user: String = config.get("user")?;
-    let password: String = config.get("password")?;
-
-    let conn = conn::Connection::new(url.clone(), user, password);
-    tx_ui_event.send(UiEvent::AddLog(format!("ping: {}", conn.ping()?)))?;
-    tx_ui_event.send(UiEvent::SetStatus(format!("connected to: {}", url)))?;
+    let player_conn = conn.clone();
+    let player_tx_ui_event = tx_ui_event.clone();
+    let player_handler = thread::spawn(move || {
+        let sink = match player::sink::open(output.as_deref()) {
+            Ok(sink) => sink,
+            Err(err) => {
+                player::fail(&rx_player_request, &player_tx_ui_event, &err);
+                return Err(err);
+            }
+        };
+        let mut player = Player::new(player_conn, sink, rx_player_request, player_tx_ui_event);
+        player.run()
+    });
 
     let library_handler = thread::spawn(|| {
-        let mut library = Library::new(conn, rx_library_request, tx_ui_event);
-        library.run().unwrap();
+        let mut library = Library::new(profile, conn, rx_library_request, tx_ui_event);
+        library.run().unwrap()
     });
 
-    library_handler.join().unwrap();
+    let unreported = library_handler.join().unwrap();
+    let played = player_handler.join().unwrap();
     ui_handler.join().unwrap();
+    // the terminal is restored now
+    for msg in unreported {
+        eprintln!("{}", msg);
+    }
 
-    Ok(())
+    played
 }
@@ -0,0 +1,232 @@
+use std::time::Duration;
+
+use color_eyre::{eyre::eyre, Result};
+use symphonia::core::{
+    audio::SampleBuffer,
+    codecs::{Decoder, DecoderOptions, CODEC_TYPE_NULL},
+    errors::Error as SymphoniaError,
+    formats::{FormatOptions, FormatReader, SeekMode, SeekTo},
+    io::{MediaSource, MediaSourceStream},
+    meta::MetadataOptions,
+    probe::Hint,
+    units::{Time, TimeBase},
+};
+
+use super::sink::AudioSpec;
+
+/// A decoded block of interleaved samples and its position in the track.
+pub struct Frame<'a> {
+    pub position: Duration,
+    pub spec: AudioSpec,
+    pub samples: &'a [f32],
+}
+
+/// An opened audio track, decoded packet by packet.
+pub struct Track {
+    format: Box<dyn FormatReader>,
+    decoder: Box<dyn Decoder>,
+    track_id: u32,
+    time_base: Option<TimeBase>,
+    duration: Option<Duration>,
+    buffer: Option<SampleBuffer<f32>>,
+    // after a seek, samples before this timestamp are discarded
+    seek_ts: Option<u64>,
+}
+
+impl Track {
+    pub fn open(source: Box<dyn MediaSource>) -> Result<Track> {
+        let mss = MediaSourceStream::new(source, Default::default());
+        let probed = symphonia::default::get_probe().format(
+            &Hint::new(),
+            mss,
+            &FormatOptions::default(),
+            &MetadataOptions::default(),
+        )?;
+        let format = probed.format;
+
+        let track = format
+            .tracks()
+            .iter()
+            .find(|t| t.codec_params.codec != CODEC_TYPE_NULL)
+            .ok_or_else(|| eyre!("no audio track found"))?;
+        let decoder = symphonia::default::get_codecs()
+            .make(&track.codec_params, &DecoderOptions::default())?;
+
+        let track_id = track.id;
+        let time_base = track.codec_params.time_base;
+        let duration = time_base
+            .zip(track.codec_params.n_frames)
+            .map(|(tb, n)| to_duration(tb.calc_time(n)));
+
+        Ok(Track {
+            format,
+            decoder,
+            track_id,
+            time_base,
+            duration,
+            buffer: None,
+            seek_ts: None,
+        })
+    }
+
+    pub fn duration(&self) -> Option<Duration> {
+        self.duration
+    }
+
+    /// Decode the next packet of the track. Returns `None` at the end of the track.
+    pub fn next_frame(&mut self) -> Result<Option<Frame<'_>>> {
+        loop {
+            let packet = match self.format.next_packet() {
+                Ok(packet) => packet,
+                Err(SymphoniaError::IoError(err))
+                    if err.kind() == std::io::ErrorKind::UnexpectedEof =>
+                {
+                    return Ok(None);
+                }
+                Err(err) => return Err(err.into()),
+            };
+            if packet.track_id() != self.track_id {
+                continue;
+            }
+
+            let decoded = match self.decoder.decode(&packet) {
+                Ok(decoded) => decoded,
+                // skip malformed packets
+                Err(SymphoniaError::DecodeError(_)) => continue,
+                Err(err) => return Err(err.into()),
+            };
+
+            let mut ts = packet.ts();
+            let mut skip = 0;
+            if let Some(seek_ts) = self.seek_ts {
+                if ts + packet.dur() <= seek_ts {
+                    continue;
+                }
+                skip = seek_ts.saturating_sub(ts) as usize;
+                ts = ts.max(seek_ts);
+                self.seek_ts = None;
+            }
+
+            let spec = *decoded.spec();
+            let capacity = decoded.capacity() as u64;
+            let n_samples = decoded.capacity() * spec.channels.count();
+            if self
+                .buffer
+                .as_ref()
+                .is_some_and(|buffer| buffer.capacity() < n_samples)
+            {
+                self.buffer = None;
+            }
+            let buffer = self
+                .buffer
+                .get_or_insert_with(|| SampleBuffer::new(capacity, spec));
+            buffer.copy_interleaved_ref(decoded);
+
+            let position = self
+                .time_base
+                .map(|tb| to_duration(tb.calc_time(ts)))
+                .unwrap_or_default();
+            let samples = buffer.samples();
+            let skip = (skip * spec.channels.count()).min(samples.len());
+
+            return Ok(Some(Frame {
+                position,
+                spec: AudioSpec {
+                    rate: spec.rate,
+                    channels: spec.channels.count() as u16,
+                },
+                samples: &samples[skip..],
+            }));
+        }
+    }
+
+    /// Seek to the given position. Returns the position actually reached.
+    pub fn seek(&mut self, position: Duration) -> Result<Duration> {
+        let seeked_to = self.format.seek(
+            SeekMode::Accurate,
+            SeekTo::Time {
+                time: Time::from(position),
+                track_id: Some(self.track_id),
+            },
+        )?;
+        self.decoder.reset();
+        self.seek_ts = Some(seeked_to.required_ts);
+
+        Ok(self
+            .time_base
+            .map(|tb| to_duration(tb.calc_time(seeked_to.required_ts)))
+            .unwrap_or(position))
+    }
+}
+
+fn to_duration(time: Time) -> Duration {
+    Duration::from_secs(time.seconds) + Duration::from_secs_f64(time.frac)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use hound::{SampleFormat, WavReader, WavSpec, WavWriter};
+
+    use super::Track;
+    use crate::player::sink::{Sink, WavSink};
+
+    fn sine_wav(rate: u32, n_frames: u32) -> Vec<u8> {
+        let spec = WavSpec {
+            channels: 2,
+            sample_rate: rate,
+            bits_per_sample: 16,
+            sample_format: SampleFormat::Int,
+        };
+        let mut data = Cursor::new(vec![]);
+        let mut writer = WavWriter::new(&mut data, spec).unwrap();
+        for i in 0..n_frames {
+            let t = i as f32 / rate as f32;
+            let sample = ((t * 440.0 * std::f32::consts::TAU).sin() * i16::MAX as f32) as i16;
+            writer.write_sample(sample).unwrap();
+            writer.write_sample(sample).unwrap();
+        }
+        writer.finalize().unwrap();
+        data.into_inner()
+    }
+
+    #[test]
+    fn decode_to_wav_sink() {
+        let mut track = Track::open(Box::new(Cursor::new(sine_wav(8000, 16000)))).unwrap();
+        assert_eq!(track.duration().unwrap().as_secs(), 2);
+
+        let path = std::env::temp_dir().join("navicon_decode_to_wav_sink.wav");
+        let mut sink = WavSink::new(path.clone());
+        let mut opened = false;
+        while let Some(frame) = track.next_frame().unwrap() {
+            if !opened {
+                sink.open(frame.spec).unwrap();
+                opened = true;
+            }
+            sink.write(frame.samples).unwrap();
+        }
+        sink.stop().unwrap();
+
+        let reader = WavReader::open(&path).unwrap();
+        assert_eq!(reader.spec().channels, 2);
+        assert_eq!(reader.spec().sample_rate, 8000);
+        assert_eq!(reader.len(), 32000);
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn seek() {
+        let mut track = Track::open(Box::new(Cursor::new(sine_wav(8000, 16000)))).unwrap();
+        let position = track.seek(std::time::Duration::from_secs(1)).unwrap();
+        assert_eq!(position.as_secs(), 1);
+        let frame = track.next_frame().unwrap().unwrap();
+        assert_eq!(frame.position.as_secs(), 1);
+
+        let mut n_samples = frame.samples.len();
+        while let Some(frame) = track.next_frame().unwrap() {
+            n_samples += frame.samples.len();
+        }
+        assert_eq!(n_samples, 16000);
+    }
+}
@@ -0,0 +1,398 @@
+mod decoder;
+pub mod request;
+mod scrobble;
+pub mod sink;
+mod stream;
+
+use std::{
+    sync::{
+        mpsc::{Receiver, RecvTimeoutError, Sender},
+        Arc,
+    },
+    time::Duration,
+};
+
+use crate::{conn::Connection, subsonic::Id, ui::event::UiEvent};
+
+use self::{
+    decoder::Track,
+    request::PlayerRequest,
+    scrobble::{Scrobble, ScrobbleRequest, Scrobbler},
+    sink::{AudioSpec, Sink},
+    stream::{Download, StreamSource},
+};
+
+use chrono::Utc;
+use color_eyre::{Report, Result};
+
+/// Data of the stream which must have arrived beyond the read position to decode the next frame.
+const BUFFER_AHEAD: u64 = 64 * 1024;
+/// How long to wait for requests while the stream has not arrived far enough.
+const BUFFER_WAIT: Duration = Duration::from_millis(20);
+
+pub struct Player {
+    conn: Connection,
+    sink: Box<dyn Sink>,
+    rx_request: Receiver<PlayerRequest>,
+    tx_ui_event: Sender<UiEvent>,
+    state: PlayerState,
+    track: Option<Track>,
+    // progress of the download of the current track
+    download: Option<Arc<Download>>,
+    // position in the track at which its stream starts, see `Connection::stream_offset`
+    offset: Duration,
+    // of the whole track, also if its stream starts at an offset
+    duration: Option<Duration>,
+    spec: Option<AudioSpec>,
+    position: Duration,
+    tx_scrobble: Sender<ScrobbleRequest>,
+    // play of the current track, until it has been played long enough to be scrobbled
+    scrobble: Option<(Scrobble, Duration)>,
+    // time the current track has been played for, not counting skipped parts
+    played: Duration,
+    // the current track has been cued, its play starts when it is resumed
+    cued: bool,
+}
+
+impl Player {
+    pub fn new(
+        conn: Connection,
+        sink: Box<dyn Sink>,
+        rx_request: Receiver<PlayerRequest>,
+        tx_ui_event: Sender<UiEvent>,
+    ) -> Player {
+        Player {
+            tx_scrobble: Scrobbler::spawn(conn.clone(), tx_ui_event.clone()),
+            scrobble: None,
+            played: Duration::ZERO,
+            cued: false,
+            conn,
+            sink,
+            rx_request,
+            tx_ui_event,
+            state: PlayerState::Stopped,
+            track: None,
+            download: None,
+            offset: Duration::ZERO,
+            duration: None,
+            spec: None,
+            position: Duration::ZERO,
+        }
+    }
+
+    /// Play until shutdown. On errors, playback stops and the error is returned once the UI has
+    /// shut down, see [`fail`].
+    pub fn run(&mut self) -> Result<()> {
+        let result = self.play_requests();
+        if let Err(err) = &result {
+            fail(&self.rx_request, &self.tx_ui_event, err);
+        }
+        result
+    }
+
+    fn play_requests(&mut self) -> Result<()> {
+        loop {
+            // only block on the request queue if there is nothing to play, and wait a little if
+            // the stream has not arrived far enough yet
+            let request = if let PlayerState::Playing(_) = self.state {
+                let timeout = if self.buffered() {
+                    Duration::ZERO
+                } else {
+                    BUFFER_WAIT
+                };
+                match self.rx_request.recv_timeout(timeout) {
+                    Ok(request) => Some(request),
+                    Err(RecvTimeoutError::Timeout) => None,
+                    Err(RecvTimeoutError::Disconnected) => break,
+                }
+            } else {
+                Some(self.rx_request.recv()?)
+            };
+
+            match request {
+                Some(PlayerRequest::Play(id)) => self.play(id)?,
+                Some(PlayerRequest::Cue(id, position)) => self.cue(id, position)?,
+                Some(PlayerRequest::TogglePause) => match self.state {
+                    PlayerState::Playing(_) => self.pause()?,
+                    PlayerState::Paused(_) => self.resume()?,
+                    PlayerState::Stopped => {}
+                },
+                Some(PlayerRequest::Stop) => self.stop()?,
+                Some(PlayerRequest::Seek(position)) => self.seek(position)?,
+                Some(PlayerRequest::SetConnection(conn)) => {
+                    self.stop()?;
+                    self.tx_scrobble
+                        .send(ScrobbleRequest::SetConnection(conn.clone()))?;
+                    self.conn = conn;
+                }
+                Some(PlayerRequest::Shutdown) => break,
+                None if self.buffered() => self.play_frame()?,
+                None => {}
+            }
+        }
+        self.sink.stop()?;
+        Ok(())
+    }
+
+    fn play(&mut self, id: Id) -> Result<()> {
+        if self.open(&id)? {
+            self.set_state(PlayerState::Playing(id.clone()))?;
+            self.tx_ui_event
+                .send(UiEvent::PlayerPosition(self.position, self.duration))?;
+            self.start_play(&id)?;
+        }
+        Ok(())
+    }
+
+    /// Open a track paused at the position. It is reported as playing once it is resumed.
+    fn cue(&mut self, id: Id, position: Duration) -> Result<()> {
+        if self.open(&id)? {
+            self.set_state(PlayerState::Paused(id))?;
+            self.seek(position)?;
+            self.cued = true;
+        }
+        Ok(())
+    }
+
+    /// Stop playback and open a track at its start. Returns `false` if it cannot be opened.
+    fn open(&mut self, id: &Id) -> Result<bool> {
+        self.stop()?;
+        match self.open_track(id, Duration::ZERO) {
+            Ok((track, download)) => {
+                self.duration = track.duration();
+                self.track = Some(track);
+                self.download = Some(download);
+                self.offset = Duration::ZERO;
+                self.position = Duration::ZERO;
+                self.played = Duration::ZERO;
+                Ok(true)
+            }
+            Err(err) => {
+                self.tx_ui_event
+                    .send(UiEvent::AddLog(format!("cannot play {}: {}", id, err)))?;
+                Ok(false)
+            }
+        }
+    }
+
+    /// Start downloading the stream of a track and open it as soon as its header has arrived.
+    fn open_track(&self, id: &Id, offset: Duration) -> Result<(Track, Arc<Download>)> {
+        let source = StreamSource::new(self.conn.stream(id, offset)?);
+        let download = source.download();
+        Ok((Track::open(Box::new(source))?, download))
+    }
+
+    /// Whether enough of the stream of the current track has arrived to decode the next frame
+    /// without waiting.
+    fn buffered(&self) -> bool {
+        self.download
+            .as_ref()
+            .is_none_or(|download| download.ready(BUFFER_AHEAD))
+    }
+
+    /// Start the play of the current track to be scrobbled, and report it as playing now.
+    fn start_play(&mut self, id: &Id) -> Result<()> {
+        self.scrobble = scrobble::threshold(self.duration).map(|threshold| {
+            let scrobble = Scrobble {
+                id: id.clone(),
+                time: Utc::now(),
+            };
+            (scrobble, threshold)
+        });
+        self.cued = false;
+        self.tx_scrobble
+            .send(ScrobbleRequest::NowPlaying(id.clone()))?;
+        Ok(())
+    }
+
+    /// Queue the current track for scrobbling once it has been played long enough.
+    fn update_scrobble(&mut self) -> Result<()> {
+        if let Some((_, threshold)) = &self.scrobble {
+            if self.played >= *threshold {
+                if let Some((scrobble, _)) = self.scrobble.take() {
+                    self.tx_scrobble.send(ScrobbleRequest::Scrobble(scrobble))?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn pause(&mut self) -> Result<()> {
+        if let PlayerState::Playing(id) = &self.state {
+            let id = id.clone();
+            self.sink.pause()?;
+            self.set_state(PlayerState::Paused(id))?;
+        }
+        Ok(())
+    }
+
+    fn resume(&mut self) -> Result<()> {
+        if let PlayerState::Paused(id) = &self.state {
+            let id = id.clone();
+            self.sink.resume()?;
+            if self.cued {
+                self.start_play(&id)?;
+            }
+            self.set_state(PlayerState::Playing(id))?;
+        }
+        Ok(())
+    }
+
+    fn stop(&mut self) -> Result<()> {
+        if self.state != PlayerState::Stopped {
+            self.sink.stop()?;
+            self.track = None;
+            self.download = None;
+            self.spec = None;
+            self.scrobble = None;
+            self.cued = false;
+            self.set_state(PlayerState::Stopped)?;
+        }
+        Ok(())
+    }
+
+    /// Seek to the position. If the server can start the stream at the position, the stream is
+    /// requested again from there, else the track seeks in the data downloaded so far.
+    fn seek(&mut self, position: Duration) -> Result<()> {
+        let id = match &self.state {
+            PlayerState::Playing(id) | PlayerState::Paused(id) => id.clone(),
+            PlayerState::Stopped => return Ok(()),
+        };
+        let offset = self.conn.stream_offset(position);
+        if offset != self.offset {
+            match self.open_track(&id, offset) {
+                Ok((track, download)) => {
+                    self.offset = stream_start(offset, self.duration, track.duration());
+                    self.track = Some(track);
+                    self.download = Some(download);
+                }
+                Err(err) => {
+                    self.tx_ui_event
+                        .send(UiEvent::AddLog(format!("cannot seek: {}", err)))?;
+                    return Ok(());
+                }
+            }
+        }
+
+        if let Some(track) = &mut self.track {
+            match track.seek(position.saturating_sub(self.offset)) {
+                Ok(position) => {
+                    self.position = self.offset + position;
+                    self.tx_ui_event
+                        .send(UiEvent::PlayerPosition(self.position, self.duration))?;
+                }
+                Err(err) => {
+                    self.tx_ui_event
+                        .send(UiEvent::AddLog(format!("cannot seek: {}", err)))?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn play_frame(&mut self) -> Result<()> {
+        let mut track = match self.track.take() {
+            Some(track) => track,
+            None => return self.stop(),
+        };
+
+        match self.output_frame(&mut track) {
+            Ok(true) => {
+                self.track = Some(track);
+            }
+            Ok(false) => {
+                if let PlayerState::Playing(id) = &self.state {
+                    let id = id.clone();
+                    self.stop()?;
+                    self.tx_ui_event.send(UiEvent::PlayerTrackEnded(id))?;
+                }
+            }
+            Err(err) => {
+                self.tx_ui_event
+                    .send(UiEvent::AddLog(format!("playback error: {}", err)))?;
+                self.stop()?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Decode the next frame of the track and write it to the sink.
+    /// Returns `false` at the end of the track.
+    fn output_frame(&mut self, track: &mut Track) -> Result<bool> {
+        let frame = match track.next_frame()? {
+            Some(frame) => frame,
+            None => return Ok(false),
+        };
+        let position = self.offset + frame.position;
+
+        if self.spec != Some(frame.spec) {
+            self.sink.open(frame.spec)?;
+            self.spec = Some(frame.spec);
+        }
+        self.sink.write(frame.samples)?;
+
+        // report the position once per second
+        if position.as_secs() != self.position.as_secs() {
+            self.tx_ui_event
+                .send(UiEvent::PlayerPosition(position, self.duration))?;
+        }
+        // a seek moves the position without playing
+        if position > self.position {
+            self.played += position - self.position;
+        }
+        self.position = position;
+        self.update_scrobble()?;
+        Ok(true)
+    }
+
+    fn set_state(&mut self, state: PlayerState) -> Result<()> {
+        self.state = state.clone();
+        self.tx_ui_event.send(UiEvent::PlayerStateChanged(state))?;
+        Ok(())
+    }
+}
+
+/// Report an error which stopped the player, and take requests until the UI shuts down, so that
+/// it keeps working without playback.
+pub fn fail(rx_request: &Receiver<PlayerRequest>, tx_ui_event: &Sender<UiEvent>, err: &Report) {
+    let _ = tx_ui_event.send(UiEvent::AddLog(format!("player stopped: {}", err)));
+    while !matches!(rx_request.recv(), Ok(PlayerRequest::Shutdown) | Err(_)) {}
+}
+
+/// Position in the track at which a stream requested from `offset` starts. Servers ignore the
+/// offset for streams which are not transcoded, which is told by the stream being about as long
+/// as the whole track instead of the rest of it.
+fn stream_start(offset: Duration, whole: Option<Duration>, stream: Option<Duration>) -> Duration {
+    match (whole, stream) {
+        (Some(whole), Some(stream))
+            if stream.abs_diff(whole) < stream.abs_diff(whole.saturating_sub(offset)) =>
+        {
+            Duration::ZERO
+        }
+        _ => offset,
+    }
+}
+
+#[derive(PartialEq, Eq, Clone)]
+pub enum PlayerState {
+    Stopped,
+    Playing(Id),
+    Paused(Id),
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::stream_start;
+
+    #[test]
+    fn ignored_offset() {
+        let secs = |secs| Some(Duration::from_secs(secs));
+        let offset = Duration::from_secs(60);
+        assert_eq!(stream_start(offset, secs(200), secs(140)), offset);
+        assert_eq!(stream_start(offset, secs(200), None), offset);
+        // the server sent the whole track
+        assert_eq!(stream_start(offset, secs(200), secs(200)), Duration::ZERO);
+    }
+}
@@ -0,0 +1,15 @@
+use std::time::Duration;
+
+use crate::{conn::Connection, subsonic::Id};
+
+pub enum PlayerRequest {
+    Play(Id),
+    /// open a track paused at the position, e.g. to continue a restored queue
+    Cue(Id, Duration),
+    TogglePause,
+    Stop,
+    Seek(Duration),
+    /// stop playback and stream from another server from now on
+    SetConnection(Connection),
+    Shutdown,
+}
@@ -0,0 +1,251 @@
+use std::{
+    collections::VecDeque,
+    fs::{self, File},
+    io::{BufReader, BufWriter, Write},
+    path::PathBuf,
+    sync::mpsc::{channel, Receiver, Sender},
+    thread,
+    time::Duration,
+};
+
+use chrono::{DateTime, Utc};
+use color_eyre::Result;
+use md5::Digest;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    conn::{Connection, ConnectionError},
+    subsonic::Id,
+    ui::event::UiEvent,
+};
+
+/// Number of scrobbles kept while the server is unreachable, older ones are dropped.
+const MAX_PENDING: usize = 1000;
+/// Tracks shorter than this are not scrobbled, as by Last.fm.
+const MIN_DURATION: Duration = Duration::from_secs(30);
+/// Tracks are scrobbled after half of their duration, but at most after this time.
+const MAX_THRESHOLD: Duration = Duration::from_secs(4 * 60);
+
+/// A play of a song, identified by the time it started.
+#[derive(Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub struct Scrobble {
+    pub id: Id,
+    pub time: DateTime<Utc>,
+}
+
+/// Time a track of the duration must have been played for to be scrobbled, `None` if it is
+/// not scrobbled at all.
+pub fn threshold(duration: Option<Duration>) -> Option<Duration> {
+    let duration = duration?;
+    if duration < MIN_DURATION {
+        None
+    } else {
+        Some((duration / 2).min(MAX_THRESHOLD))
+    }
+}
+
+pub enum ScrobbleRequest {
+    /// report the song as playing now, and retry the pending scrobbles
+    NowPlaying(Id),
+    Scrobble(Scrobble),
+    /// submit to the server of another profile, with its pending scrobbles
+    SetConnection(Connection),
+}
+
+/// Submits scrobbles on a thread of its own, so that a slow server does not hold up
+/// playback.
+pub struct Scrobbler {
+    conn: Connection,
+    scrobbles: ScrobbleQueue,
+    rx_request: Receiver<ScrobbleRequest>,
+    tx_ui_event: Sender<UiEvent>,
+}
+
+impl Scrobbler {
+    /// Start the scrobbler thread, which ends when the returned sender is dropped. Scrobbles
+    /// are saved before they are submitted, so the thread is not waited for on exit.
+    pub fn spawn(conn: Connection, tx_ui_event: Sender<UiEvent>) -> Sender<ScrobbleRequest> {
+        let (tx_request, rx_request) = channel();
+        thread::spawn(move || {
+            let mut scrobbler = Scrobbler {
+                scrobbles: ScrobbleQueue::load(&conn),
+                conn,
+                rx_request,
+                tx_ui_event,
+            };
+            scrobbler.run();
+        });
+        tx_request
+    }
+
+    fn run(&mut self) {
+        while let Ok(request) = self.rx_request.recv() {
+            match request {
+                ScrobbleRequest::NowPlaying(id) => {
+                    if let Err(err) = self.conn.scrobble(&id, None, false) {
+                        self.log(format!("cannot report now playing: {}", err));
+                        continue;
+                    }
+                    self.submit();
+                }
+                ScrobbleRequest::Scrobble(scrobble) => {
+                    if let Err(err) = self.scrobbles.push(scrobble) {
+                        self.log(format!("cannot save scrobbles: {}", err));
+                    }
+                    self.submit();
+                }
+                ScrobbleRequest::SetConnection(conn) => {
+                    self.scrobbles = ScrobbleQueue::load(&conn);
+                    self.conn = conn;
+                }
+            }
+        }
+    }
+
+    fn submit(&mut self) {
+        if let Err(err) = self.scrobbles.submit(&self.conn) {
+            self.log(format!(
+                "cannot scrobble, {} plays kept for later: {}",
+                self.scrobbles.len(),
+                err
+            ));
+        }
+    }
+
+    fn log(&self, msg: String) {
+        // the UI may be gone while a submission is still running at exit
+        let _ = self.tx_ui_event.send(UiEvent::AddLog(msg));
+    }
+}
+
+/// Scrobbles waiting to be submitted, persisted per server and user.
+pub struct ScrobbleQueue {
+    pending: VecDeque<Scrobble>,
+    // location of the persisted queue, if any
+    file: Option<PathBuf>,
+}
+
+impl ScrobbleQueue {
+    /// Load the queue of the server and user of the connection, empty if none was saved.
+    pub fn load(conn: &Connection) -> ScrobbleQueue {
+        let file = scrobble_file(conn.url(), conn.user());
+        let pending = file
+            .as_ref()
+            .and_then(|file| File::open(file).ok())
+            .and_then(|f| serde_json::from_reader(BufReader::new(f)).ok())
+            .unwrap_or_default();
+        ScrobbleQueue { pending, file }
+    }
+
+    pub fn len(&self) -> usize {
+        self.pending.len()
+    }
+
+    pub fn push(&mut self, scrobble: Scrobble) -> Result<()> {
+        self.pending.push_back(scrobble);
+        while self.pending.len() > MAX_PENDING {
+            self.pending.pop_front();
+        }
+        self.save()
+    }
+
+    /// Submit the pending scrobbles in order. Scrobbles rejected by the server are dropped,
+    /// on other errors the remaining scrobbles are kept for the next attempt.
+    pub fn submit(&mut self, conn: &Connection) -> Result<()> {
+        if self.pending.is_empty() {
+            return Ok(());
+        }
+        let mut result = Ok(());
+        while let Some(scrobble) = self.pending.front() {
+            match conn.scrobble(&scrobble.id, Some(scrobble.time), true) {
+                Ok(_) => {}
+                Err(err) if err.downcast_ref::<ConnectionError>().is_some() => {}
+                Err(err) => {
+                    result = Err(err);
+                    break;
+                }
+            }
+            self.pending.pop_front();
+        }
+        self.save()?;
+        result
+    }
+
+    fn save(&self) -> Result<()> {
+        let file = match &self.file {
+            Some(file) => file,
+            None => return Ok(()),
+        };
+        if self.pending.is_empty() {
+            if file.exists() {
+                fs::remove_file(file)?;
+            }
+            return Ok(());
+        }
+        if let Some(dir) = file.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let mut writer = BufWriter::new(File::create(file)?);
+        serde_json::to_writer(&mut writer, &self.pending)?;
+        writer.flush()?;
+        Ok(())
+    }
+}
+
+/// Location of the pending scrobbles for a server and user, in the XDG data directory.
+fn scrobble_file(url: &str, user: &str) -> Option<PathBuf> {
+    let key = md5::Md5::digest(format!("{}\n{}", url, user));
+    dirs::data_dir().map(|dir| {
+        dir.join("navicon")
+            .join(format!("scrobbles-{:032x}.json", key))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::VecDeque, time::Duration};
+
+    use chrono::{TimeZone, Utc};
+
+    use super::{threshold, Scrobble, ScrobbleQueue, MAX_PENDING};
+
+    #[test]
+    fn thresholds() {
+        assert_eq!(threshold(None), None);
+        assert_eq!(threshold(Some(Duration::from_secs(20))), None);
+        assert_eq!(
+            threshold(Some(Duration::from_secs(164))),
+            Some(Duration::from_secs(82))
+        );
+        assert_eq!(
+            threshold(Some(Duration::from_secs(1200))),
+            Some(Duration::from_secs(240))
+        );
+    }
+
+    #[test]
+    fn bounded_persisted() {
+        let path = std::env::temp_dir()
+            .join(format!("navicon-scrobble-test-{}", std::process::id()))
+            .join("scrobbles.json");
+        let mut queue = ScrobbleQueue {
+            pending: VecDeque::new(),
+            file: Some(path.clone()),
+        };
+        for i in 0..MAX_PENDING + 2 {
+            queue
+                .push(Scrobble {
+                    id: i.to_string(),
+                    time: Utc.timestamp_opt(i as i64, 0).unwrap(),
+                })
+                .unwrap();
+        }
+        assert_eq!(queue.len(), MAX_PENDING);
+
+        let pending: VecDeque<Scrobble> =
+            serde_json::from_reader(std::fs::File::open(&path).unwrap()).unwrap();
+        std::fs::remove_dir_all(path.parent().unwrap()).unwrap();
+        assert_eq!(pending.len(), MAX_PENDING);
+        assert_eq!(pending[0].id, "2");
+    }
+}
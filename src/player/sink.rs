@@ -0,0 +1,299 @@
+use std::{
+    fs::File,
+    io::BufWriter,
+    path::PathBuf,
+    thread,
+    time::{Duration, Instant},
+};
+
+use color_eyre::{eyre::bail, Result};
+use hound::{SampleFormat, WavSpec, WavWriter};
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct AudioSpec {
+    pub rate: u32,
+    pub channels: u16,
+}
+
+/// Destination for decoded audio.
+pub trait Sink {
+    /// Prepare the sink for samples of the given format.
+    fn open(&mut self, spec: AudioSpec) -> Result<()>;
+    /// Write interleaved samples. May block until the sink accepts more data.
+    fn write(&mut self, samples: &[f32]) -> Result<()>;
+    fn pause(&mut self) -> Result<()>;
+    fn resume(&mut self) -> Result<()>;
+    /// Discard any buffered samples and release the output.
+    fn stop(&mut self) -> Result<()>;
+}
+
+/// Open the sink selected by the `output` setting.
+///
+/// * `null`: discard all audio
+/// * `wav:<path>`: write the audio of the last played track to a WAV file
+/// * `default` or unset: the default audio device (with the `cpal` feature)
+pub fn open(output: Option<&str>) -> Result<Box<dyn Sink>> {
+    match output {
+        Some("null") => Ok(Box::new(NullSink::default())),
+        Some(output) if output.starts_with("wav:") => {
+            Ok(Box::new(WavSink::new(PathBuf::from(&output[4..]))))
+        }
+        None | Some("default") => default_sink(),
+        Some(output) => bail!("unknown output: {}", output),
+    }
+}
+
+#[cfg(feature = "cpal")]
+fn default_sink() -> Result<Box<dyn Sink>> {
+    Ok(Box::new(cpal_sink::CpalSink::new()?))
+}
+
+#[cfg(not(feature = "cpal"))]
+fn default_sink() -> Result<Box<dyn Sink>> {
+    Ok(Box::new(NullSink::default()))
+}
+
+/// Paces sinks without an audio device, so that tracks take as long as they would to play.
+/// Otherwise tracks would end as soon as they are decoded.
+#[derive(Default)]
+struct RealTime {
+    spec: Option<AudioSpec>,
+    // when playback started or was resumed, and the length of the audio written since
+    start: Option<(Instant, Duration)>,
+}
+
+impl RealTime {
+    fn open(&mut self, spec: AudioSpec) {
+        self.spec = Some(spec);
+        self.start = None;
+    }
+
+    /// Block until the samples would have been played.
+    fn write(&mut self, samples: &[f32]) {
+        let Some(spec) = self.spec else {
+            return;
+        };
+        let frames = samples.len() as u64 / spec.channels.max(1) as u64;
+        let length = Duration::from_micros(frames * 1_000_000 / spec.rate.max(1) as u64);
+        let (start, written) = self.start.get_or_insert((Instant::now(), Duration::ZERO));
+        *written += length;
+        let end = *start + *written;
+        thread::sleep(end.saturating_duration_since(Instant::now()));
+    }
+
+    /// Stop the clock, it restarts with the next write.
+    fn pause(&mut self) {
+        self.start = None;
+    }
+}
+
+/// Discards all audio, in real time.
+#[derive(Default)]
+pub struct NullSink {
+    clock: RealTime,
+}
+
+impl Sink for NullSink {
+    fn open(&mut self, spec: AudioSpec) -> Result<()> {
+        self.clock.open(spec);
+        Ok(())
+    }
+
+    fn write(&mut self, samples: &[f32]) -> Result<()> {
+        self.clock.write(samples);
+        Ok(())
+    }
+
+    fn pause(&mut self) -> Result<()> {
+        self.clock.pause();
+        Ok(())
+    }
+
+    fn resume(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    fn stop(&mut self) -> Result<()> {
+        self.clock.pause();
+        Ok(())
+    }
+}
+
+/// Writes the audio to a WAV file, in real time.
+pub struct WavSink {
+    path: PathBuf,
+    writer: Option<WavWriter<BufWriter<File>>>,
+    clock: RealTime,
+}
+
+impl WavSink {
+    pub fn new(path: PathBuf) -> WavSink {
+        WavSink {
+            path,
+            writer: None,
+            clock: RealTime::default(),
+        }
+    }
+}
+
+impl Sink for WavSink {
+    fn open(&mut self, spec: AudioSpec) -> Result<()> {
+        self.stop()?;
+        self.clock.open(spec);
+        let wav_spec = WavSpec {
+            channels: spec.channels,
+            sample_rate: spec.rate,
+            bits_per_sample: 32,
+            sample_format: SampleFormat::Float,
+        };
+        self.writer = Some(WavWriter::create(&self.path, wav_spec)?);
+        Ok(())
+    }
+
+    fn write(&mut self, samples: &[f32]) -> Result<()> {
+        if let Some(writer) = &mut self.writer {
+            for sample in samples {
+                writer.write_sample(*sample)?;
+            }
+        }
+        self.clock.write(samples);
+        Ok(())
+    }
+
+    fn pause(&mut self) -> Result<()> {
+        self.clock.pause();
+        Ok(())
+    }
+
+    fn resume(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    fn stop(&mut self) -> Result<()> {
+        self.clock.pause();
+        if let Some(writer) = self.writer.take() {
+            writer.finalize()?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(feature = "cpal")]
+mod cpal_sink {
+    use std::{
+        collections::VecDeque,
+        sync::{Arc, Condvar, Mutex},
+    };
+
+    use color_eyre::{eyre::eyre, Result};
+    use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
+
+    use super::{AudioSpec, Sink};
+
+    /// Number of samples buffered ahead of the audio device.
+    const QUEUE_CAPACITY: usize = 1 << 15;
+
+    #[derive(Default)]
+    struct SampleQueue {
+        samples: Mutex<VecDeque<f32>>,
+        drained: Condvar,
+    }
+
+    pub struct CpalSink {
+        device: cpal::Device,
+        stream: Option<cpal::Stream>,
+        queue: Arc<SampleQueue>,
+    }
+
+    impl CpalSink {
+        pub fn new() -> Result<CpalSink> {
+            let device = cpal::default_host()
+                .default_output_device()
+                .ok_or_else(|| eyre!("no audio output device available"))?;
+            Ok(CpalSink {
+                device,
+                stream: None,
+                queue: Arc::new(SampleQueue::default()),
+            })
+        }
+    }
+
+    impl Sink for CpalSink {
+        fn open(&mut self, spec: AudioSpec) -> Result<()> {
+            self.stop()?;
+            let config = cpal::StreamConfig {
+                channels: spec.channels,
+                sample_rate: cpal::SampleRate(spec.rate),
+                buffer_size: cpal::BufferSize::Default,
+            };
+            let queue = self.queue.clone();
+            let stream = self.device.build_output_stream(
+                &config,
+                move |data: &mut [f32], _| {
+                    let mut samples = queue.samples.lock().unwrap();
+                    for out in data.iter_mut() {
+                        *out = samples.pop_front().unwrap_or(0.0);
+                    }
+                    queue.drained.notify_one();
+                },
+                // stream errors (e.g. buffer underruns) are not fatal
+                |_| {},
+                None,
+            )?;
+            stream.play()?;
+            self.stream = Some(stream);
+            Ok(())
+        }
+
+        fn write(&mut self, samples: &[f32]) -> Result<()> {
+            let mut queue = self.queue.samples.lock().unwrap();
+            while queue.len() + samples.len() > QUEUE_CAPACITY && !queue.is_empty() {
+                queue = self.queue.drained.wait(queue).unwrap();
+            }
+            queue.extend(samples);
+            Ok(())
+        }
+
+        fn pause(&mut self) -> Result<()> {
+            if let Some(stream) = &self.stream {
+                stream.pause()?;
+            }
+            Ok(())
+        }
+
+        fn resume(&mut self) -> Result<()> {
+            if let Some(stream) = &self.stream {
+                stream.play()?;
+            }
+            Ok(())
+        }
+
+        fn stop(&mut self) -> Result<()> {
+            self.stream = None;
+            self.queue.samples.lock().unwrap().clear();
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use super::{AudioSpec, NullSink, Sink};
+
+    #[test]
+    fn null_sink_real_time() {
+        let mut sink = NullSink::default();
+        sink.open(AudioSpec {
+            rate: 1000,
+            channels: 2,
+        })
+        .unwrap();
+        let start = Instant::now();
+        // 2 x 50 ms of stereo audio
+        sink.write(&[0.0; 100]).unwrap();
+        sink.write(&[0.0; 100]).unwrap();
+        assert!(start.elapsed() >= Duration::from_millis(100));
+    }
+}
@@ -0,0 +1,182 @@
+use std::{
+    io::{self, Read, Seek, SeekFrom},
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc, Condvar, Mutex,
+    },
+    thread,
+};
+
+use symphonia::core::io::MediaSource;
+
+/// Size of the chunks read from the server.
+const CHUNK_SIZE: usize = 64 * 1024;
+
+/// A stream downloaded on a separate thread, readable and seekable as far as it has arrived.
+/// Reading beyond that blocks until more data arrives.
+pub struct StreamSource {
+    download: Arc<Download>,
+    pos: u64,
+}
+
+/// Progress of a stream download, shared with the source reading it.
+pub struct Download {
+    state: Mutex<DownloadState>,
+    arrived: Condvar,
+    // position of the source in the stream
+    pos: AtomicU64,
+    // the source has been dropped, the download can stop
+    cancelled: AtomicBool,
+}
+
+#[derive(Default)]
+struct DownloadState {
+    data: Vec<u8>,
+    done: bool,
+    error: Option<String>,
+}
+
+impl StreamSource {
+    pub fn new(mut reader: Box<dyn Read + Send + Sync>) -> StreamSource {
+        let download = Arc::new(Download {
+            state: Mutex::new(DownloadState::default()),
+            arrived: Condvar::new(),
+            pos: AtomicU64::new(0),
+            cancelled: AtomicBool::new(false),
+        });
+        let shared = download.clone();
+        thread::spawn(move || {
+            let mut chunk = vec![0; CHUNK_SIZE];
+            loop {
+                let result = reader.read(&mut chunk);
+                let mut state = shared.state.lock().unwrap();
+                match result {
+                    Ok(0) => state.done = true,
+                    Ok(n) => state.data.extend_from_slice(&chunk[..n]),
+                    Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+                    Err(err) => {
+                        state.error = Some(err.to_string());
+                        state.done = true;
+                    }
+                }
+                shared.arrived.notify_all();
+                if state.done || shared.cancelled.load(Ordering::Relaxed) {
+                    break;
+                }
+            }
+        });
+        StreamSource { download, pos: 0 }
+    }
+
+    /// The progress of the download, to check for data without reading it.
+    pub fn download(&self) -> Arc<Download> {
+        self.download.clone()
+    }
+}
+
+impl Download {
+    /// Whether `ahead` bytes beyond the read position have arrived, or the download has ended.
+    pub fn ready(&self, ahead: u64) -> bool {
+        let state = self.state.lock().unwrap();
+        state.done || state.data.len() as u64 >= self.pos.load(Ordering::Relaxed) + ahead
+    }
+}
+
+impl Drop for StreamSource {
+    fn drop(&mut self) {
+        self.download.cancelled.store(true, Ordering::Relaxed);
+    }
+}
+
+impl Read for StreamSource {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let state = self.download.state.lock().unwrap();
+        let state = self
+            .download
+            .arrived
+            .wait_while(state, |state| {
+                !state.done && state.data.len() as u64 <= self.pos
+            })
+            .unwrap();
+        let start = (self.pos as usize).min(state.data.len());
+        if start == state.data.len() {
+            if let Some(err) = &state.error {
+                return Err(io::Error::other(err.clone()));
+            }
+        }
+        let n = buf.len().min(state.data.len() - start);
+        buf[..n].copy_from_slice(&state.data[start..start + n]);
+        self.pos += n as u64;
+        self.download.pos.store(self.pos, Ordering::Relaxed);
+        Ok(n)
+    }
+}
+
+impl Seek for StreamSource {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let pos = match pos {
+            SeekFrom::Start(pos) => Some(pos),
+            SeekFrom::Current(offset) => self.pos.checked_add_signed(offset),
+            SeekFrom::End(offset) => {
+                // the length is only known once the download has ended
+                let state = self.download.state.lock().unwrap();
+                let state = self
+                    .download
+                    .arrived
+                    .wait_while(state, |state| !state.done)
+                    .unwrap();
+                (state.data.len() as u64).checked_add_signed(offset)
+            }
+        };
+        self.pos = pos
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "seek before the start"))?;
+        self.download.pos.store(self.pos, Ordering::Relaxed);
+        Ok(self.pos)
+    }
+}
+
+impl MediaSource for StreamSource {
+    fn is_seekable(&self) -> bool {
+        true
+    }
+
+    fn byte_len(&self) -> Option<u64> {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{self, Read, Seek, SeekFrom};
+
+    use super::StreamSource;
+
+    /// A reader handing out one byte per read, like a slow server.
+    struct Trickle(Vec<u8>);
+
+    impl Read for Trickle {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            if self.0.is_empty() || buf.is_empty() {
+                return Ok(0);
+            }
+            buf[0] = self.0.remove(0);
+            Ok(1)
+        }
+    }
+
+    #[test]
+    fn read_while_downloading() {
+        let data: Vec<u8> = (0..=255).collect();
+        let mut source = StreamSource::new(Box::new(Trickle(data.clone())));
+        let mut read = vec![];
+        source.read_to_end(&mut read).unwrap();
+        assert_eq!(read, data);
+        assert!(source.download().ready(1));
+
+        assert_eq!(source.seek(SeekFrom::End(-6)).unwrap(), 250);
+        let mut buf = [0; 10];
+        assert_eq!(source.read(&mut buf).unwrap(), 6);
+        assert_eq!(buf[..6], [250, 251, 252, 253, 254, 255]);
+        assert!(source.seek(SeekFrom::Current(-300)).is_err());
+    }
+}
@@ -0,0 +1,269 @@
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+
+use crate::{library::Song, subsonic::Id};
+
+pub struct Queue {
+    entries: Vec<QueueEntry>,
+    current: Option<usize>,
+}
+
+#[derive(Clone)]
+pub struct QueueEntry {
+    pub id: Id,
+    pub song: Song,
+}
+
+/// A queue stored on the server, to continue playback on another device.
+pub struct SavedQueue {
+    pub entries: Vec<QueueEntry>,
+    pub current: Option<usize>,
+    /// position in the current entry
+    pub position: Duration,
+    /// the client which saved the queue, and when
+    pub changed_by: String,
+    pub changed: DateTime<Utc>,
+}
+
+/// Where songs are added to the queue.
+#[derive(Clone, Copy)]
+pub enum EnqueueMode {
+    Append,
+    Next,
+    /// insert after the current entry and start playing
+    Play,
+}
+
+impl Queue {
+    pub fn new() -> Queue {
+        Queue {
+            entries: vec![],
+            current: None,
+        }
+    }
+
+    pub fn entries(&self) -> &[QueueEntry] {
+        &self.entries
+    }
+
+    pub fn current_index(&self) -> Option<usize> {
+        self.current
+    }
+
+    pub fn current(&self) -> Option<&QueueEntry> {
+        self.current.and_then(|i| self.entries.get(i))
+    }
+
+    /// Replace all entries, e.g. with a queue restored from the server.
+    pub fn restore(&mut self, entries: Vec<QueueEntry>, current: Option<usize>) {
+        self.current = current.filter(|&current| current < entries.len());
+        self.entries = entries;
+    }
+
+    pub fn enqueue(&mut self, entries: Vec<QueueEntry>, mode: EnqueueMode) {
+        match mode {
+            EnqueueMode::Append => self.append(entries),
+            EnqueueMode::Next | EnqueueMode::Play => self.insert_after_current(entries),
+        }
+    }
+
+    pub fn append(&mut self, entries: Vec<QueueEntry>) {
+        self.entries.extend(entries);
+    }
+
+    pub fn insert_after_current(&mut self, entries: Vec<QueueEntry>) {
+        let index = self.current.map(|i| i + 1).unwrap_or(0);
+        self.entries.splice(index..index, entries);
+    }
+
+    pub fn move_entry(&mut self, from: usize, to: usize) {
+        if from >= self.entries.len() || to >= self.entries.len() {
+            return;
+        }
+        let entry = self.entries.remove(from);
+        self.entries.insert(to, entry);
+
+        if let Some(current) = self.current {
+            self.current = Some(if current == from {
+                to
+            } else if from < current && current <= to {
+                current - 1
+            } else if to <= current && current < from {
+                current + 1
+            } else {
+                current
+            });
+        }
+    }
+
+    /// Remove the entry at `index`. If it is the current entry, the cursor moves to the
+    /// previous entry, so that `next` continues with the entry following the removed one.
+    pub fn remove(&mut self, index: usize) {
+        if index >= self.entries.len() {
+            return;
+        }
+        self.entries.remove(index);
+
+        if let Some(current) = self.current {
+            if index <= current {
+                self.current = current.checked_sub(1);
+            }
+        }
+    }
+
+    /// Replace the song of all entries with the id, e.g. after it has been starred.
+    pub fn update_song(&mut self, id: &Id, song: &Song) {
+        for entry in self.entries.iter_mut().filter(|entry| &entry.id == id) {
+            entry.song = song.clone();
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+        self.current = None;
+    }
+
+    /// Advance to the next entry. Returns `None` at the end of the queue.
+    pub fn next(&mut self) -> Option<&QueueEntry> {
+        let next = self.current.map(|i| i + 1).unwrap_or(0);
+        self.select(next)
+    }
+
+    /// Go back to the previous entry. Returns `None` at the start of the queue.
+    pub fn previous(&mut self) -> Option<&QueueEntry> {
+        let previous = self.current?.checked_sub(1)?;
+        self.select(previous)
+    }
+
+    /// Make the entry at `index` the current entry.
+    pub fn select(&mut self, index: usize) -> Option<&QueueEntry> {
+        if index < self.entries.len() {
+            self.current = Some(index);
+            self.entries.get(index)
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{EnqueueMode, Queue, QueueEntry};
+    use crate::library::{Marks, Song};
+
+    fn entries(ids: &[&str]) -> Vec<QueueEntry> {
+        ids.iter()
+            .map(|id| QueueEntry {
+                id: id.to_string(),
+                song: Song {
+                    title: (*id).into(),
+                    track_number: None,
+                    duration: None,
+                    genre: None,
+                    cover_art: None,
+                    marks: Marks::default(),
+                },
+            })
+            .collect()
+    }
+
+    fn ids(queue: &Queue) -> Vec<&str> {
+        queue.entries().iter().map(|e| e.id.as_str()).collect()
+    }
+
+    #[test]
+    fn next_previous() {
+        let mut queue = Queue::new();
+        assert!(queue.next().is_none());
+
+        queue.append(entries(&["a", "b"]));
+        assert_eq!(queue.next().unwrap().id, "a");
+        assert!(queue.previous().is_none());
+        assert_eq!(queue.next().unwrap().id, "b");
+        assert!(queue.next().is_none());
+        assert_eq!(queue.current().unwrap().id, "b");
+        assert_eq!(queue.previous().unwrap().id, "a");
+    }
+
+    #[test]
+    fn enqueue_next() {
+        let mut queue = Queue::new();
+        queue.enqueue(entries(&["a", "b"]), EnqueueMode::Next);
+        queue.enqueue(entries(&["c"]), EnqueueMode::Next);
+        assert_eq!(ids(&queue), ["c", "a", "b"]);
+
+        queue.select(1);
+        queue.enqueue(entries(&["d", "e"]), EnqueueMode::Next);
+        queue.enqueue(entries(&["f"]), EnqueueMode::Append);
+        assert_eq!(ids(&queue), ["c", "a", "d", "e", "b", "f"]);
+        assert_eq!(queue.next().unwrap().id, "d");
+    }
+
+    #[test]
+    fn move_entry() {
+        let mut queue = Queue::new();
+        queue.append(entries(&["a", "b", "c", "d"]));
+        queue.select(1);
+
+        queue.move_entry(1, 3);
+        assert_eq!(ids(&queue), ["a", "c", "d", "b"]);
+        assert_eq!(queue.current_index(), Some(3));
+
+        queue.move_entry(0, 3);
+        assert_eq!(ids(&queue), ["c", "d", "b", "a"]);
+        assert_eq!(queue.current_index(), Some(2));
+
+        queue.move_entry(3, 0);
+        assert_eq!(ids(&queue), ["a", "c", "d", "b"]);
+        assert_eq!(queue.current_index(), Some(3));
+    }
+
+    #[test]
+    fn remove() {
+        let mut queue = Queue::new();
+        queue.append(entries(&["a", "b", "c", "d"]));
+        queue.select(2);
+
+        queue.remove(3);
+        assert_eq!(queue.current().unwrap().id, "c");
+        queue.remove(0);
+        assert_eq!(queue.current().unwrap().id, "c");
+
+        queue.remove(1);
+        assert_eq!(ids(&queue), ["b"]);
+        assert_eq!(queue.current().unwrap().id, "b");
+        assert!(queue.next().is_none());
+
+        queue.clear();
+        assert!(queue.current().is_none());
+    }
+
+    #[test]
+    fn restore() {
+        let mut queue = Queue::new();
+        queue.append(entries(&["a"]));
+        queue.restore(entries(&["b", "c"]), Some(1));
+        assert_eq!(ids(&queue), ["b", "c"]);
+        assert_eq!(queue.current().unwrap().id, "c");
+
+        queue.restore(entries(&["d"]), Some(1));
+        assert!(queue.current().is_none());
+    }
+
+    #[test]
+    fn update_song() {
+        let mut queue = Queue::new();
+        queue.append(entries(&["a", "b", "a"]));
+        let mut song = queue.entries()[0].song.clone();
+        song.marks.starred = true;
+
+        queue.update_song(&"a".to_string(), &song);
+        let starred: Vec<_> = queue
+            .entries()
+            .iter()
+            .map(|e| e.song.marks.starred)
+            .collect();
+        assert_eq!(starred, [true, false, true]);
+    }
+}
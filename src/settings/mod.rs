@@ -0,0 +1,562 @@
+pub mod password;
+
+use std::{
+    collections::HashMap,
+    fs,
+    io::{self, BufRead, IsTerminal, Write},
+    path::{Path, PathBuf},
+};
+
+use color_eyre::{
+    eyre::{bail, eyre},
+    Result,
+};
+use config::{Config, Environment, File, FileFormat, Value};
+use secrecy::Secret;
+use serde::Serialize;
+
+use crate::conn::{AuthMode, Connection, ResponseFormat};
+
+use self::password::{Keyring, PasswordSource, SystemKeyring};
+
+/// Prefix of environment variables overriding settings, e.g. `NAVICON_URL`.
+const ENV_PREFIX: &str = "NAVICON";
+/// Name of the profile made up by the top-level keys if no profiles are configured.
+const DEFAULT_PROFILE: &str = "default";
+
+pub struct Settings {
+    /// all configured profiles, sorted by name
+    pub profiles: Vec<Profile>,
+    /// index of the profile to connect to
+    pub active: usize,
+    pub output: Option<String>,
+    /// how cover art is drawn, detected from the terminal if unset
+    pub graphics: Option<String>,
+}
+
+/// A server and the credentials to log in.
+#[derive(Clone)]
+pub struct Profile {
+    pub name: String,
+    pub url: String,
+    pub user: String,
+    pub password_source: PasswordSource,
+    pub auth_mode: AuthMode,
+    pub response_format: ResponseFormat,
+}
+
+impl Profile {
+    /// Get the password, e.g. from the keyring.
+    pub fn password(&self, keyring: &dyn Keyring) -> Result<Secret<String>> {
+        self.password_source
+            .resolve(keyring, &keyring_account(&self.url, &self.user))
+            .map_err(|err| {
+                eyre!(
+                    "cannot get the password for profile `{}`: {}",
+                    self.name,
+                    err
+                )
+            })
+    }
+
+    /// Make a connection to the server, getting the password first.
+    pub fn connection(&self, keyring: &dyn Keyring) -> Result<Connection> {
+        Ok(Connection::new(
+            self.url.clone(),
+            self.user.clone(),
+            self.password(keyring)?,
+            self.auth_mode,
+            self.response_format,
+        ))
+    }
+}
+
+/// Account under which the password of a server and user is stored in the keyring.
+fn keyring_account(url: &str, user: &str) -> String {
+    format!("{}@{}", user, url)
+}
+
+/// Default location of the configuration file, `$XDG_CONFIG_HOME/navicon/config.toml`.
+pub fn default_path() -> Result<PathBuf> {
+    dirs::config_dir()
+        .map(|dir| dir.join("navicon").join("config.toml"))
+        .ok_or_else(|| eyre!("could not determine the configuration directory"))
+}
+
+/// Load the settings, offering the first-run setup if there is no configuration file yet.
+pub fn load_or_setup(path: &Path, profile: Option<&str>) -> Result<Settings> {
+    if !path.exists()
+        && io::stdin().is_terminal()
+        && confirm(&format!(
+            "No configuration found at {}. Run setup?",
+            path.display()
+        ))?
+    {
+        return setup(path, &SystemKeyring);
+    }
+    load(path, profile)
+}
+
+/// Load the settings from the configuration file (if it exists).
+///
+/// Servers are configured as named profiles in `[profiles.<name>]` tables, with top-level keys
+/// as defaults for all profiles. Without profile tables, the top-level keys make up the single
+/// profile `default`. The profile to connect to is given by `profile`, or else by the `profile`
+/// key. `NAVICON_*` environment variables override the settings of this profile.
+///
+/// The password is given by one of `password`, `password_command`, `password_file` or
+/// `keyring = true`. It is only resolved when connecting with the profile, see
+/// [`Profile::connection`].
+pub fn load(path: &Path, profile: Option<&str>) -> Result<Settings> {
+    let file = Config::builder()
+        .add_source(File::from(path).format(FileFormat::Toml).required(false))
+        .build()?;
+    let env = Config::builder()
+        .add_source(Environment::with_prefix(ENV_PREFIX))
+        .build()?;
+
+    let tables: HashMap<String, HashMap<String, Value>> = match file.get_table("profiles") {
+        Ok(profiles) => profiles
+            .into_iter()
+            .map(|(name, table)| Ok((name, table.into_table()?)))
+            .collect::<Result<_, config::ConfigError>>()?,
+        Err(_) => HashMap::from([(DEFAULT_PROFILE.to_string(), HashMap::new())]),
+    };
+    let mut names: Vec<_> = tables.keys().cloned().collect();
+    names.sort();
+    if names.is_empty() {
+        bail!("no profiles configured in {}", path.display());
+    }
+
+    let active_name = match profile
+        .map(String::from)
+        .or_else(|| env.get_string("profile").ok())
+        .or_else(|| file.get_string("profile").ok())
+    {
+        Some(name) => name,
+        None => names[0].clone(),
+    };
+    let active = names
+        .iter()
+        .position(|name| *name == active_name)
+        .ok_or_else(|| {
+            eyre!(
+                "unknown profile `{}`, configured profiles: {}",
+                active_name,
+                names.join(", ")
+            )
+        })?;
+
+    let profiles = names
+        .iter()
+        .map(|name| {
+            let is_active = *name == active_name;
+            let from_env = |key: &str| env.get_string(key).ok().filter(|_| is_active);
+            let from_profile = |key: &str| {
+                tables[name]
+                    .get(key)
+                    .and_then(|value| value.clone().into_string().ok())
+            };
+            let from_file = |key: &str| file.get_string(key).ok();
+            let lookup = |key: &str| {
+                from_env(key)
+                    .or_else(|| from_profile(key))
+                    .or_else(|| from_file(key))
+            };
+            let missing = |keys: &[&str]| {
+                let env_keys: Vec<_> = keys
+                    .iter()
+                    .map(|key| format!("{}_{}", ENV_PREFIX, key.to_uppercase()))
+                    .collect();
+                let keys: Vec<_> = keys.iter().map(|key| format!("`{}`", key)).collect();
+                eyre!(
+                    "missing setting {} for profile `{}`: set it in {}{}",
+                    keys.join(" or "),
+                    name,
+                    path.display(),
+                    if is_active {
+                        format!(" or via {}", env_keys.join(" / "))
+                    } else {
+                        String::new()
+                    }
+                )
+            };
+            let required = |key: &str| lookup(key).ok_or_else(|| missing(&[key]));
+
+            let url = required("url")?;
+            let user = required("user")?;
+            let auth_mode = match lookup("auth_mode") {
+                Some(auth_mode) => auth_mode.parse().map_err(|err| {
+                    eyre!(
+                        "invalid setting `auth_mode` for profile `{}`: {}",
+                        name,
+                        err
+                    )
+                })?,
+                None => AuthMode::Token,
+            };
+            let response_format = match lookup("response_format") {
+                Some(response_format) => response_format.parse().map_err(|err| {
+                    eyre!(
+                        "invalid setting `response_format` for profile `{}`: {}",
+                        name,
+                        err
+                    )
+                })?,
+                None => ResponseFormat::Json,
+            };
+            // a password setting of a profile replaces any password setting at the top level
+            let sources = [
+                password_sources(from_env),
+                password_sources(from_profile),
+                password_sources(from_file),
+            ]
+            .into_iter()
+            .find(|sources| !sources.is_empty())
+            .unwrap_or_default();
+            let password_source = match sources.as_slice() {
+                [source] => source.clone(),
+                [] => {
+                    return Err(missing(&[
+                        "password",
+                        "password_command",
+                        "password_file",
+                        "keyring",
+                    ]))
+                }
+                _ => bail!("more than one password setting for profile `{}`", name),
+            };
+
+            Ok(Profile {
+                name: name.clone(),
+                url,
+                user,
+                password_source,
+                auth_mode,
+                response_format,
+            })
+        })
+        .collect::<Result<_>>()?;
+
+    Ok(Settings {
+        profiles,
+        active,
+        output: env
+            .get_string("output")
+            .or_else(|_| file.get_string("output"))
+            .ok(),
+        graphics: env
+            .get_string("graphics")
+            .or_else(|_| file.get_string("graphics"))
+            .ok(),
+    })
+}
+
+/// All password settings among the given settings.
+fn password_sources(lookup: impl Fn(&str) -> Option<String>) -> Vec<PasswordSource> {
+    [
+        lookup("password").map(PasswordSource::Plain),
+        lookup("password_command").map(PasswordSource::Command),
+        lookup("password_file").map(|path| PasswordSource::File(path.into())),
+        lookup("keyring")
+            .filter(|keyring| keyring == "true")
+            .map(|_| PasswordSource::Keyring),
+    ]
+    .into_iter()
+    .flatten()
+    .collect()
+}
+
+/// Contents of the configuration file written by the setup.
+#[derive(Serialize)]
+struct SetupConfig<'a> {
+    url: &'a str,
+    user: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    password: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    keyring: Option<bool>,
+}
+
+/// Interactively ask for the server and credentials, check them and write the configuration
+/// file. The password is stored in the keyring if possible.
+fn setup(path: &Path, keyring: &dyn Keyring) -> Result<Settings> {
+    loop {
+        let url = prompt("Server URL (e.g. https://navidrome.example.com)")?;
+        let user = prompt("User")?;
+        let password = rpassword::prompt_password("Password: ")?;
+
+        let profile = Profile {
+            name: DEFAULT_PROFILE.to_string(),
+            url,
+            user,
+            password_source: PasswordSource::Plain(password.clone()),
+            auth_mode: AuthMode::Token,
+            response_format: ResponseFormat::Json,
+        };
+        match profile.connection(keyring)?.ping() {
+            Ok(true) => {
+                let mut config = SetupConfig {
+                    url: &profile.url,
+                    user: &profile.user,
+                    password: None,
+                    keyring: None,
+                };
+                if confirm("Store the password in the system keyring?")? {
+                    match keyring
+                        .set_password(&keyring_account(&profile.url, &profile.user), &password)
+                    {
+                        Ok(()) => config.keyring = Some(true),
+                        Err(err) => println!("Could not access the keyring: {}", err),
+                    }
+                }
+                if config.keyring.is_none() {
+                    config.password = Some(&password);
+                }
+                save(path, &config)?;
+                println!("Configuration written to {}", path.display());
+                return Ok(Settings {
+                    profiles: vec![profile],
+                    active: 0,
+                    output: None,
+                    graphics: None,
+                });
+            }
+            Ok(false) => println!("The server did not accept the ping."),
+            Err(err) => println!("Could not connect: {}", err),
+        }
+        if !confirm("Try again?")? {
+            bail!("setup cancelled");
+        }
+    }
+}
+
+fn save(path: &Path, config: &SetupConfig) -> Result<()> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    // the file may contain the password, it must not be readable by others at any time
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    options
+        .open(path)?
+        .write_all(toml::to_string(config)?.as_bytes())?;
+    Ok(())
+}
+
+fn prompt(question: &str) -> Result<String> {
+    loop {
+        print!("{}: ", question);
+        io::stdout().flush()?;
+        let mut answer = String::new();
+        if io::stdin().lock().read_line(&mut answer)? == 0 {
+            bail!("setup cancelled");
+        }
+        let answer = answer.trim();
+        if !answer.is_empty() {
+            return Ok(answer.to_string());
+        }
+    }
+}
+
+fn confirm(question: &str) -> Result<bool> {
+    print!("{} [Y/n] ", question);
+    io::stdout().flush()?;
+    let mut answer = String::new();
+    io::stdin().lock().read_line(&mut answer)?;
+    Ok(!answer.trim().to_lowercase().starts_with('n'))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs, path::PathBuf, sync::Mutex};
+
+    use secrecy::ExposeSecret;
+
+    use super::{
+        load,
+        password::{mock::MockKeyring, Keyring},
+        AuthMode,
+    };
+
+    // the tests read `NAVICON_*` environment variables, which are shared by all threads
+    static ENV_LOCK: Mutex<()> = Mutex::new(());
+
+    /// Directory for the configuration files of a test. It is removed at the end of the test,
+    /// as the files contain passwords.
+    struct TestDir(PathBuf);
+
+    impl TestDir {
+        fn new(test: &str) -> TestDir {
+            let dir =
+                env::temp_dir().join(format!("navicon-settings-{}-{}", std::process::id(), test));
+            fs::create_dir_all(&dir).unwrap();
+            TestDir(dir)
+        }
+
+        fn config_file(&self, name: &str, content: &str) -> PathBuf {
+            let path = self.0.join(name);
+            fs::write(&path, content).unwrap();
+            path
+        }
+    }
+
+    impl Drop for TestDir {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    #[test]
+    fn load_settings() {
+        let _lock = ENV_LOCK.lock().unwrap();
+        let keyring = MockKeyring::default();
+        let dir = TestDir::new("load_settings");
+        let path = dir.config_file(
+            "single.toml",
+            "url = \"https://example.com\"\nuser = \"alice\"\n",
+        );
+        let err = load(&path, None).err().unwrap().to_string();
+        assert!(err.contains("`password`"));
+        assert!(err.contains("NAVICON_PASSWORD"));
+
+        env::set_var("NAVICON_PASSWORD", "sesame");
+        env::set_var("NAVICON_USER", "bob");
+        let settings = load(&path, None);
+        env::remove_var("NAVICON_PASSWORD");
+        env::remove_var("NAVICON_USER");
+
+        let settings = settings.unwrap();
+        assert_eq!(settings.profiles.len(), 1);
+        let profile = &settings.profiles[settings.active];
+        assert_eq!(profile.name, "default");
+        assert_eq!(profile.url, "https://example.com");
+        assert_eq!(profile.user, "bob");
+        assert_eq!(
+            profile.password(&keyring).unwrap().expose_secret(),
+            "sesame"
+        );
+        assert!(settings.output.is_none());
+    }
+
+    #[test]
+    fn load_profiles() {
+        let _lock = ENV_LOCK.lock().unwrap();
+        let keyring = MockKeyring::default();
+        let dir = TestDir::new("load_profiles");
+        let path = dir.config_file(
+            "profiles.toml",
+            r#"
+            user = "alice"
+            password = "sesame"
+            profile = "office"
+            output = "null"
+            graphics = "blocks"
+
+            [profiles.home]
+            url = "http://home.example.com"
+
+            [profiles.office]
+            url = "https://office.example.com"
+            user = "a.liddell"
+            auth_mode = "password"
+            "#,
+        );
+
+        let settings = load(&path, None).unwrap();
+        let names: Vec<_> = settings.profiles.iter().map(|p| p.name.as_str()).collect();
+        assert_eq!(names, ["home", "office"]);
+        assert_eq!(settings.active, 1);
+        assert_eq!(settings.profiles[0].user, "alice");
+        assert_eq!(settings.profiles[1].user, "a.liddell");
+        assert_eq!(
+            settings.profiles[1]
+                .password(&keyring)
+                .unwrap()
+                .expose_secret(),
+            "sesame"
+        );
+        assert_eq!(settings.profiles[0].auth_mode, AuthMode::Token);
+        assert_eq!(settings.profiles[1].auth_mode, AuthMode::Password);
+        assert_eq!(settings.output.as_deref(), Some("null"));
+        assert_eq!(settings.graphics.as_deref(), Some("blocks"));
+
+        assert_eq!(load(&path, Some("home")).unwrap().active, 0);
+        let err = load(&path, Some("test")).err().unwrap().to_string();
+        assert!(err.contains("home, office"));
+    }
+
+    #[test]
+    fn load_empty_profiles() {
+        let _lock = ENV_LOCK.lock().unwrap();
+        let dir = TestDir::new("load_empty_profiles");
+        let path = dir.config_file("empty.toml", "user = \"alice\"\n[profiles]\n");
+        let err = load(&path, None).err().unwrap().to_string();
+        assert!(err.contains("no profiles configured"));
+    }
+
+    #[test]
+    fn load_password_sources() {
+        let _lock = ENV_LOCK.lock().unwrap();
+        let keyring = MockKeyring::default();
+        let dir = TestDir::new("load_password_sources");
+        let password_file = dir.config_file("password", "open sesame\n");
+        let path = dir.config_file(
+            "passwords.toml",
+            &format!(
+                r#"
+                user = "alice"
+                password = "top-level sesame"
+
+                [profiles.command]
+                url = "https://command.example.com"
+                password_command = "echo sesame"
+
+                [profiles.file]
+                url = "https://file.example.com"
+                password_file = "{}"
+
+                [profiles.keyring]
+                url = "https://keyring.example.com"
+                keyring = true
+
+                [profiles.plain]
+                url = "https://plain.example.com"
+                "#,
+                password_file.display()
+            ),
+        );
+
+        // passwords are only resolved when connecting, a missing one does not fail loading
+        let settings = load(&path, None).unwrap();
+        let err = settings.profiles[2]
+            .password(&keyring)
+            .err()
+            .unwrap()
+            .to_string();
+        assert!(err.contains("`keyring`"));
+
+        keyring
+            .set_password("alice@https://keyring.example.com", "keyring sesame")
+            .unwrap();
+        let passwords: Vec<_> = settings
+            .profiles
+            .iter()
+            .map(|p| p.password(&keyring).unwrap().expose_secret().clone())
+            .collect();
+        assert_eq!(
+            passwords,
+            [
+                "sesame",
+                "open sesame",
+                "keyring sesame",
+                "top-level sesame"
+            ]
+        );
+    }
+}
@@ -0,0 +1,131 @@
+use std::{fs, path::PathBuf, process::Command};
+
+use color_eyre::{eyre::bail, Result};
+use secrecy::Secret;
+
+/// Service name under which passwords are stored in the keyring.
+const KEYRING_SERVICE: &str = "navicon";
+
+/// Where the password of a profile comes from.
+#[derive(Clone)]
+pub enum PasswordSource {
+    /// stored in the configuration file
+    Plain(String),
+    /// printed by a shell command, e.g. `pass show navidrome`
+    Command(String),
+    /// the content of a file
+    File(PathBuf),
+    /// the system keyring, stored for the account `<user>@<url>`
+    Keyring,
+}
+
+impl PasswordSource {
+    pub fn resolve(&self, keyring: &dyn Keyring, account: &str) -> Result<Secret<String>> {
+        let password = match self {
+            PasswordSource::Plain(password) => password.clone(),
+            PasswordSource::Command(command) => {
+                let output = Command::new("sh").arg("-c").arg(command).output()?;
+                if !output.status.success() {
+                    // stdout might contain parts of the secret, only report stderr
+                    bail!(
+                        "`{}` failed ({}): {}",
+                        command,
+                        output.status,
+                        String::from_utf8_lossy(&output.stderr).trim()
+                    );
+                }
+                String::from_utf8(output.stdout)?
+            }
+            PasswordSource::File(path) => fs::read_to_string(path)?,
+            PasswordSource::Keyring => keyring.get_password(account)?,
+        };
+        // commands and files usually end with a newline which is not part of the password
+        Ok(Secret::new(
+            password.trim_end_matches(['\n', '\r']).to_string(),
+        ))
+    }
+}
+
+/// Access to stored passwords.
+pub trait Keyring {
+    fn get_password(&self, account: &str) -> Result<String>;
+    fn set_password(&self, account: &str, password: &str) -> Result<()>;
+}
+
+/// The keyring of the desktop session, accessed via the Secret Service D-Bus API.
+pub struct SystemKeyring;
+
+impl Keyring for SystemKeyring {
+    fn get_password(&self, account: &str) -> Result<String> {
+        Ok(keyring::Entry::new(KEYRING_SERVICE, account)?.get_password()?)
+    }
+
+    fn set_password(&self, account: &str, password: &str) -> Result<()> {
+        Ok(keyring::Entry::new(KEYRING_SERVICE, account)?.set_password(password)?)
+    }
+}
+
+#[cfg(test)]
+pub mod mock {
+    use std::{cell::RefCell, collections::HashMap};
+
+    use color_eyre::{eyre::eyre, Result};
+
+    use super::Keyring;
+
+    /// In-memory keyring for tests.
+    #[derive(Default)]
+    pub struct MockKeyring {
+        passwords: RefCell<HashMap<String, String>>,
+    }
+
+    impl Keyring for MockKeyring {
+        fn get_password(&self, account: &str) -> Result<String> {
+            self.passwords
+                .borrow()
+                .get(account)
+                .cloned()
+                .ok_or_else(|| eyre!("no password stored for {}", account))
+        }
+
+        fn set_password(&self, account: &str, password: &str) -> Result<()> {
+            self.passwords
+                .borrow_mut()
+                .insert(account.to_string(), password.to_string());
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use secrecy::ExposeSecret;
+
+    use super::{mock::MockKeyring, Keyring, PasswordSource};
+
+    #[test]
+    fn resolve() {
+        let keyring = MockKeyring::default();
+        let resolve = |source: PasswordSource| {
+            source
+                .resolve(&keyring, "alice@https://example.com")
+                .map(|password| password.expose_secret().clone())
+        };
+
+        assert_eq!(
+            resolve(PasswordSource::Plain("sesame".into())).unwrap(),
+            "sesame"
+        );
+        assert_eq!(
+            resolve(PasswordSource::Command("echo sesame".into())).unwrap(),
+            "sesame"
+        );
+        assert!(resolve(PasswordSource::Command("echo sesame; false".into())).is_err());
+
+        assert!(resolve(PasswordSource::Keyring).is_err());
+        keyring
+            .set_password("alice@https://example.com", "open sesame")
+            .unwrap();
+        assert_eq!(resolve(PasswordSource::Keyring).unwrap(), "open sesame");
+    }
+}
@@ -1,5 +1,8 @@
+// the API types mirror the Subsonic schema, fields the client does not read are marked with
+// `#[allow(dead_code)]`
+
 use chrono::{DateTime, Utc};
-use serde::Deserialize;
+use serde::{Deserialize, Deserializer};
 
 pub type Id = String;
 
@@ -8,27 +11,103 @@ pub type Id = String;
 pub struct SubsonicResponse {
     pub status: String,
     pub version: String,
+    /// OpenSubsonic server attributes
+    #[serde(default)]
+    pub open_subsonic: bool,
+    #[serde(rename = "type")]
+    pub server_type: Option<String>,
+    pub server_version: Option<String>,
 
     #[serde(rename = "$value")]
     pub data: Option<SubsonicData>,
 }
 
+/// The JSON format wraps the response in an object with a single `subsonic-response` key.
+#[derive(Deserialize)]
+pub struct JsonEnvelope {
+    #[serde(rename = "subsonic-response")]
+    pub response: JsonResponse,
+}
+
+/// In JSON, the data is a sibling of the status attributes instead of a child element.
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct JsonResponse {
+    pub status: String,
+    pub version: String,
+    #[serde(default)]
+    pub open_subsonic: bool,
+    #[serde(rename = "type")]
+    pub server_type: Option<String>,
+    pub server_version: Option<String>,
+
+    #[serde(flatten)]
+    pub data: Option<SubsonicData>,
+}
+
+impl From<JsonResponse> for SubsonicResponse {
+    fn from(value: JsonResponse) -> Self {
+        SubsonicResponse {
+            status: value.status,
+            version: value.version,
+            open_subsonic: value.open_subsonic,
+            server_type: value.server_type,
+            server_version: value.server_version,
+            data: value.data,
+        }
+    }
+}
+
+/// Music folder ids are integers in the schema, which are numbers in JSON.
+fn id_from_string_or_number<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Id, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum StringOrNumber {
+        String(String),
+        Number(u64),
+    }
+    Ok(match StringOrNumber::deserialize(deserializer)? {
+        StringOrNumber::String(s) => s,
+        StringOrNumber::Number(n) => n.to_string(),
+    })
+}
+
 #[derive(Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub enum SubsonicData {
+    #[allow(dead_code)]
     License(License),
     MusicFolders(MusicFolders),
+    Indexes(Indexes),
+    Genres(Genres),
     Artists(Artists),
     Artist(Artist),
     Album(Album),
+    Song(Child),
+    AlbumList2(AlbumList),
+    ArtistInfo2(ArtistInfo),
+    RandomSongs(Songs),
+    SongsByGenre(Songs),
+    TopSongs(Songs),
+    SearchResult3(SearchResult3),
+    Starred2(Starred2),
+    Playlists(Playlists),
+    Playlist(Playlist),
+    PlayQueue(PlayQueue),
+    Lyrics(Lyrics),
+    LyricsList(LyricsList),
+    OpenSubsonicExtensions(Vec<OpenSubsonicExtension>),
     Error(Error),
 }
 
 #[derive(Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct License {
+    #[allow(dead_code)]
     pub valid: bool,
+    #[allow(dead_code)]
     pub email: Option<String>,
+    #[allow(dead_code)]
     pub license_expires: Option<String>,
 }
 
@@ -41,15 +120,71 @@ pub struct MusicFolders {
 #[derive(Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct MusicFolder {
+    #[serde(deserialize_with = "id_from_string_or_number")]
     pub id: Id,
     pub name: Option<String>,
 }
 
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Indexes {
+    /// milliseconds since the epoch
+    pub last_modified: u64,
+    #[allow(dead_code)]
+    pub ignored_articles: String,
+    #[serde(default)]
+    #[allow(dead_code)]
+    pub index: Vec<DirectoryIndex>,
+    /// files in the root of the music folders
+    #[serde(default)]
+    #[allow(dead_code)]
+    pub child: Vec<Child>,
+}
+
+/// An index of the file structure, see [`Index`] for the ID3 tag based one.
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DirectoryIndex {
+    #[allow(dead_code)]
+    pub name: String,
+    #[serde(default)]
+    #[allow(dead_code)]
+    pub artist: Vec<DirectoryArtist>,
+}
+
+/// A top-level directory of the file structure, the id is the one of the directory.
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DirectoryArtist {
+    #[allow(dead_code)]
+    pub id: Id,
+    #[allow(dead_code)]
+    pub name: String,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Genres {
+    #[serde(default)]
+    pub genre: Vec<Genre>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Genre {
+    /// the name is the text content in XML
+    #[serde(rename = "$value", alias = "value")]
+    pub name: String,
+    pub song_count: usize,
+    pub album_count: usize,
+}
+
 #[derive(Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct Artists {
     #[serde(default)]
     pub index: Vec<Index>,
+    #[allow(dead_code)]
     pub ignored_articles: String,
 }
 
@@ -66,7 +201,10 @@ pub struct Index {
 pub struct Artist {
     pub id: Id,
     pub name: String,
+    #[allow(dead_code)]
     pub album_count: usize,
+    pub starred: Option<DateTime<Utc>>,
+    pub user_rating: Option<u8>,
     #[serde(default)]
     pub album: Vec<Album>,
 }
@@ -76,13 +214,20 @@ pub struct Artist {
 pub struct Album {
     pub id: Id,
     pub name: String,
+    #[allow(dead_code)]
     pub artist: Option<String>,
     pub artist_id: Option<Id>,
+    #[allow(dead_code)]
     pub song_count: usize,
+    #[allow(dead_code)]
     pub duration: usize,
     pub created: DateTime<Utc>,
+    #[allow(dead_code)]
     pub year: Option<usize>,
     pub genre: Option<String>,
+    pub cover_art: Option<Id>,
+    pub starred: Option<DateTime<Utc>>,
+    pub user_rating: Option<u8>,
 
     #[serde(default)]
     pub song: Vec<Child>,
@@ -92,16 +237,180 @@ pub struct Album {
 #[serde(rename_all = "camelCase")]
 pub struct Child {
     pub id: Id,
+    #[allow(dead_code)]
     pub parent: Option<Id>,
+    #[allow(dead_code)]
     pub is_dir: bool,
     pub title: String,
+    #[allow(dead_code)]
     pub album: Option<String>,
+    pub album_id: Option<Id>,
     pub artist: Option<String>,
     pub track: Option<usize>,
+    #[allow(dead_code)]
     pub year: Option<usize>,
     pub genre: Option<String>,
+    #[allow(dead_code)]
     pub content_type: Option<String>,
     pub duration: Option<usize>,
+    pub cover_art: Option<Id>,
+    pub starred: Option<DateTime<Utc>>,
+    pub user_rating: Option<u8>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AlbumList {
+    #[serde(default)]
+    pub album: Vec<Album>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ArtistInfo {
+    pub biography: Option<String>,
+    pub music_brainz_id: Option<String>,
+    #[allow(dead_code)]
+    pub last_fm_url: Option<String>,
+    #[allow(dead_code)]
+    pub small_image_url: Option<String>,
+    #[allow(dead_code)]
+    pub medium_image_url: Option<String>,
+    #[allow(dead_code)]
+    pub large_image_url: Option<String>,
+    #[serde(default)]
+    pub similar_artist: Vec<Artist>,
+}
+
+/// A plain list of songs, as returned by e.g. `getRandomSongs` and `getTopSongs`.
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Songs {
+    #[serde(default)]
+    pub song: Vec<Child>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SearchResult3 {
+    #[serde(default)]
+    pub artist: Vec<Artist>,
+    #[serde(default)]
+    pub album: Vec<Album>,
+    #[serde(default)]
+    pub song: Vec<Child>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Starred2 {
+    #[serde(default)]
+    pub artist: Vec<Artist>,
+    #[serde(default)]
+    pub album: Vec<Album>,
+    #[serde(default)]
+    pub song: Vec<Child>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Playlists {
+    #[serde(default)]
+    pub playlist: Vec<Playlist>,
+}
+
+/// A playlist, with its songs only as returned by `getPlaylist`.
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Playlist {
+    pub id: Id,
+    pub name: String,
+    #[allow(dead_code)]
+    pub comment: Option<String>,
+    pub owner: Option<String>,
+    #[allow(dead_code)]
+    pub public: Option<bool>,
+    pub song_count: usize,
+    pub duration: usize,
+    #[allow(dead_code)]
+    pub created: DateTime<Utc>,
+    pub changed: DateTime<Utc>,
+
+    #[serde(default)]
+    pub entry: Vec<Child>,
+}
+
+/// The play queue stored on the server by `savePlayQueue`.
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PlayQueue {
+    /// id of the current song
+    pub current: Option<Id>,
+    /// position in the current song in milliseconds
+    pub position: Option<u64>,
+    #[allow(dead_code)]
+    pub username: String,
+    pub changed: DateTime<Utc>,
+    /// the client which saved the queue
+    pub changed_by: String,
+
+    #[serde(default)]
+    pub entry: Vec<Child>,
+}
+
+/// Plain lyrics found by artist and title with `getLyrics`.
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Lyrics {
+    #[allow(dead_code)]
+    pub artist: Option<String>,
+    #[allow(dead_code)]
+    pub title: Option<String>,
+    /// the text is the text content in XML, missing if no lyrics were found
+    #[serde(rename = "$value", alias = "value", default)]
+    pub value: String,
+}
+
+/// The lyrics of a song returned by `getLyricsBySongId` (OpenSubsonic `songLyrics`).
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LyricsList {
+    #[serde(default)]
+    pub structured_lyrics: Vec<StructuredLyrics>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StructuredLyrics {
+    #[allow(dead_code)]
+    pub display_artist: Option<String>,
+    #[allow(dead_code)]
+    pub display_title: Option<String>,
+    #[allow(dead_code)]
+    pub lang: String,
+    /// milliseconds to shift all lines by, positive values show them earlier
+    pub offset: Option<i64>,
+    pub synced: bool,
+    #[serde(default)]
+    pub line: Vec<LyricsLine>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LyricsLine {
+    /// start of the line in milliseconds, only for synced lyrics
+    pub start: Option<u64>,
+    #[serde(rename = "$value", alias = "value", default)]
+    pub value: String,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OpenSubsonicExtension {
+    pub name: String,
+    #[serde(default)]
+    #[allow(dead_code)]
+    pub versions: Vec<u32>,
 }
 
 #[derive(Deserialize)]
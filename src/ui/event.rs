@@ -1,10 +1,54 @@
-use crate::library::{LibraryItem, LibraryItemKey};
+use std::time::Duration;
+
+use crate::{
+    conn::Connection,
+    cover::Cover,
+    library::{ArtistInfo, LibraryItem, LibraryItemKey},
+    lyrics::Lyrics,
+    player::PlayerState,
+    queue::{EnqueueMode, SavedQueue},
+    subsonic::Id,
+};
 
 pub type LibraryRequestResult = Vec<(LibraryItemKey, LibraryItem)>;
 
+/// The children of a library item, along with the item itself and its parent.
+pub struct LibraryNode {
+    pub this: Option<LibraryItem>,
+    pub parent: Option<LibraryItemKey>,
+    pub children: LibraryRequestResult,
+    /// whether further pages of children can be requested with `GetMore`
+    pub more: bool,
+}
+
 pub enum UiEvent {
     AddLog(String),
     SetStatus(String),
-    LibraryGetChildrenComplete(LibraryItemKey, LibraryRequestResult),
+    /// the library connected to the server and discovered its capabilities
+    LibraryConnected(Connection),
+    LibraryGetChildrenComplete(LibraryItemKey, LibraryNode),
     LibraryFindEntriesComplete(usize, LibraryRequestResult),
+    LibraryFindEntriesFailed(usize, String),
+    LibraryGetSongsComplete(EnqueueMode, LibraryRequestResult),
+    /// the item has been changed, e.g. starred
+    LibraryItemChanged(LibraryItemKey, LibraryItem),
+    /// the ids and names of the playlists
+    LibraryPlaylistsComplete(Vec<(Id, String)>),
+    /// the play queue stored on the server, `None` if there is none
+    LibraryPlayQueueComplete(Option<SavedQueue>),
+    LibraryCoverArtComplete(Id, Cover),
+    LibraryCoverArtFailed(Id, String),
+    LibraryArtistInfoComplete(Id, ArtistInfo),
+    LibraryArtistInfoFailed(Id, String),
+    /// the lyrics of a song, `None` if the server has none
+    LibraryLyricsComplete(Id, Option<Lyrics>),
+    LibraryLyricsFailed(Id, String),
+    /// the music folders by id and name, and the selected one
+    LibraryMusicFoldersComplete(Vec<(Id, String)>, Option<Id>),
+    /// a `GetChildren` or `GetSongs` request for the item failed
+    LibraryRequestFailed(LibraryItemKey, String),
+    PlayerStateChanged(PlayerState),
+    PlayerPosition(Duration, Option<Duration>),
+    #[allow(dead_code)]
+    PlayerTrackEnded(Id),
 }
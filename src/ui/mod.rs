@@ -1,44 +1,75 @@
 pub mod event;
 mod model;
+mod view;
 
 use color_eyre::Result;
 use crossterm::{
-    event::{DisableMouseCapture, EnableMouseCapture, Event, KeyCode},
+    event::{DisableMouseCapture, EnableMouseCapture, Event, KeyCode, KeyEvent},
     execute,
     terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen},
 };
 use std::{
-    io,
+    io::{self, Write},
     sync::mpsc::{Receiver, Sender},
     thread,
-    time::Duration,
-};
-use tui::{
-    backend::{Backend, CrosstermBackend},
-    layout::{Constraint, Direction, Layout},
-    style::{Modifier, Style},
-    text::{Span, Text},
-    widgets::{Block, Borders, List, ListItem, Paragraph, Wrap},
-    Frame, Terminal,
+    time::{Duration, Instant},
 };
+use tui::{backend::CrosstermBackend, layout::Rect, Terminal};
 
-use crate::library::{request::LibraryRequest, LibraryItemKey};
+use crate::{
+    cover::{self, Graphics, CELL_HEIGHT, CELL_WIDTH, KITTY_CLEAR},
+    library::{request::LibraryRequest, LibraryItem, LibraryItemKey},
+    player::{request::PlayerRequest, PlayerState},
+    queue::{EnqueueMode, QueueEntry, SavedQueue},
+    settings::{password::SystemKeyring, Profile},
+    subsonic::Id,
+};
 
-use self::event::{LibraryRequestResult, UiEvent};
+use self::{
+    event::{LibraryRequestResult, UiEvent},
+    model::{
+        ArtistInfoContext, Context, Focus, LibraryContext, LyricsContext, Model, Picker,
+        PickerKind, Resolvable,
+    },
+};
 
 const TICK: Duration = Duration::from_millis(200);
+const SEEK_STEP: Duration = Duration::from_secs(10);
+/// Interval in which the play queue is saved on the server during playback.
+const QUEUE_SAVE_INTERVAL: Duration = Duration::from_secs(30);
 
 pub struct Ui {
     terminal: Terminal<CrosstermBackend<io::Stdout>>,
-    app_state: AppState,
+    model: Model,
+    profiles: Vec<Profile>,
+    active_profile: usize,
+    // the music folders offered by the open folder picker, `None` for all folders
+    music_folders: Vec<Option<Id>>,
+    // the playlists offered by the open playlist picker, `None` for a new playlist
+    playlists: Vec<Option<Id>>,
+    // the item to add to the playlist chosen in the picker
+    playlist_target: Option<LibraryItemKey>,
+    // the playlist to delete once confirmed
+    playlist_to_delete: Option<Id>,
+    // whether the play queue stored on the server has been received, it is not overwritten
+    // before
+    queue_restored: bool,
+    queue_saved: Instant,
+    // the cover art written to the terminal as an image, and where
+    cover_drawn: Option<(Id, Rect)>,
     tx_library_request: Sender<LibraryRequest>,
+    tx_player_request: Sender<PlayerRequest>,
     rx_ui_event: Receiver<UiEvent>,
     redraw: bool,
 }
 
 impl Ui {
     pub fn new(
+        profiles: Vec<Profile>,
+        active_profile: usize,
+        graphics: Graphics,
         tx_library_request: Sender<LibraryRequest>,
+        tx_player_request: Sender<PlayerRequest>,
         rx_ui_event: Receiver<UiEvent>,
     ) -> Result<Ui> {
         enable_raw_mode()?;
@@ -47,43 +78,970 @@ impl Ui {
         let backend = CrosstermBackend::new(stdout);
         let mut terminal = Terminal::new(backend)?;
 
-        let app_state = AppState {
-            status: String::new(),
-            log: String::new(),
-            library_view: vec![],
-        };
+        let mut model = Model::new();
+        model.graphics = graphics;
 
-        terminal.draw(|f| ui(f, &app_state))?;
+        terminal.draw(|f| view::draw(f, &model))?;
 
         Ok(Ui {
             terminal,
-            app_state,
+            model,
+            profiles,
+            active_profile,
+            music_folders: vec![],
+            playlists: vec![],
+            playlist_target: None,
+            playlist_to_delete: None,
+            queue_restored: false,
+            queue_saved: Instant::now(),
+            cover_drawn: None,
             tx_library_request,
+            tx_player_request,
             rx_ui_event,
             redraw: false,
         })
     }
 
-    fn add_log(&mut self, msg: &str) {
-        self.app_state.log.push_str(msg);
-        self.app_state.log.push('\n');
+    fn page_size(&self) -> usize {
+        self.terminal.size().map(view::page_size).unwrap_or(1)
+    }
+
+    /// Show the children of `key` in the library view, selecting `select_key` once loaded.
+    fn open_library(
+        &mut self,
+        key: LibraryItemKey,
+        select_key: Option<LibraryItemKey>,
+    ) -> Result<()> {
+        let mut library = LibraryContext::new(key.clone());
+        library.select_key = select_key;
+        self.model.context = Context::Library(library);
+        self.tx_library_request
+            .send(LibraryRequest::GetChildren(key))?;
+        Ok(())
+    }
+
+    /// Open a child of the current library view. The current view is kept as its parent until
+    /// the library has answered, so that it can be left while loading or after an error.
+    fn descend(&mut self, key: LibraryItemKey) -> Result<()> {
+        let parent = self.model.library().key.clone();
+        self.open_library(key, None)?;
+        self.model.library_mut().parent = Some(parent);
+        Ok(())
     }
 
-    fn set_status(&mut self, status: &str) {
-        self.app_state.status = String::from(status);
+    /// Fetch the children of the current library view again, bypassing the cache. Also used
+    /// to retry after an error.
+    fn refresh_library(&mut self) -> Result<()> {
+        let library = self.model.library_mut();
+        if !library.children.is_pending() {
+            library.children = Resolvable::Pending;
+            self.tx_library_request
+                .send(LibraryRequest::Refresh(library.key.clone()))?;
+        }
+        Ok(())
+    }
+
+    /// Request the next page of a paged library view once the selection is on its last page.
+    fn load_more(&mut self) -> Result<()> {
+        let page_size = self.page_size();
+        let library = self.model.library_mut();
+        if library.more && library.selected + page_size >= library.children.len() {
+            // request the page only once
+            library.more = false;
+            self.tx_library_request
+                .send(LibraryRequest::GetMore(library.key.clone()))?;
+        }
+        Ok(())
+    }
+
+    /// Descend into the selected item.
+    fn open_selected(&mut self) -> Result<()> {
+        let key = match self.model.library().selected_child() {
+            Some((key, _)) => key.clone(),
+            None => return Ok(()),
+        };
+        match key {
+            LibraryItemKey::AlbumList(_)
+            | LibraryItemKey::Decades
+            | LibraryItemKey::Genres
+            | LibraryItemKey::Starred
+            | LibraryItemKey::RandomSongs
+            | LibraryItemKey::Playlists
+            | LibraryItemKey::Playlist(_)
+            | LibraryItemKey::Genre(_)
+            | LibraryItemKey::GenreSongs(_)
+            | LibraryItemKey::Artist(_)
+            | LibraryItemKey::Album(_) => self.descend(key)?,
+            LibraryItemKey::Song(_) => self.enqueue_selected(EnqueueMode::Play)?,
+            LibraryItemKey::Root => {}
+        }
+        Ok(())
     }
 
-    fn set_library_view(&mut self, items: LibraryRequestResult) {
-        self.app_state.library_view = items
+    /// Go back to the parent of the current library view, or to the root if the parent is not
+    /// known, e.g. while a parent opened by going back is still loading.
+    fn close_current(&mut self) -> Result<()> {
+        let library = self.model.library();
+        let parent = match &library.parent {
+            Some(parent) => parent.clone(),
+            None if library.key != LibraryItemKey::Root => LibraryItemKey::Root,
+            None => return Ok(()),
+        };
+        let key = library.key.clone();
+        self.open_library(parent, Some(key))
+    }
+
+    fn enqueue_selected(&mut self, mode: EnqueueMode) -> Result<()> {
+        if let Some((key, _)) = self.model.library().selected_child() {
+            self.tx_library_request
+                .send(LibraryRequest::GetSongs(key.clone(), mode))?;
+        }
+        Ok(())
+    }
+
+    /// Add songs sent by the library to the queue.
+    fn enqueue_songs(&mut self, songs: LibraryRequestResult, mode: EnqueueMode) -> Result<()> {
+        let entries = songs
             .into_iter()
-            .map(|(id, item)| UiLibraryItem {
-                id,
-                text: item.to_string(),
+            .filter_map(|(key, item)| match (key, item) {
+                (LibraryItemKey::Song(id), LibraryItem::Song(song)) => {
+                    Some(QueueEntry { id, song })
+                }
+                _ => None,
             })
             .collect();
-        self.app_state
-            .library_view
-            .sort_by(|a, b| a.text.cmp(&b.text));
+        self.model.queue.enqueue(entries, mode);
+        if let EnqueueMode::Play = mode {
+            self.play_next()?;
+        }
+        Ok(())
+    }
+
+    /// Request the info of the artist shown in the library if it changed.
+    fn update_artist_info(&mut self) -> Result<()> {
+        let wanted = match &self.model.library().key {
+            LibraryItemKey::Artist(id) => Some(id),
+            _ => None,
+        };
+        if wanted == self.model.artist_info.as_ref().map(|info| &info.id) {
+            return Ok(());
+        }
+        self.model.artist_info = wanted.cloned().map(ArtistInfoContext::new);
+        match &self.model.artist_info {
+            Some(info) => self
+                .tx_library_request
+                .send(LibraryRequest::GetArtistInfo(info.id.clone()))?,
+            None => {
+                if self.model.focus == Focus::ArtistInfo {
+                    self.model.focus = Focus::Library;
+                }
+            }
+        }
+        self.redraw = true;
+        Ok(())
+    }
+
+    /// Open the selected similar artist, or play the selected top song.
+    fn open_selected_artist_info(&mut self) -> Result<()> {
+        match self
+            .model
+            .artist_info
+            .as_ref()
+            .and_then(|info| info.selected_entry())
+        {
+            Some((key @ LibraryItemKey::Artist(_), _)) => self.descend(key.clone()),
+            Some(_) => self.enqueue_selected_artist_info(EnqueueMode::Play),
+            None => Ok(()),
+        }
+    }
+
+    /// Enqueue the songs of the selected similar artist, or the selected top song.
+    fn enqueue_selected_artist_info(&mut self, mode: EnqueueMode) -> Result<()> {
+        let Some(entry) = self
+            .model
+            .artist_info
+            .as_ref()
+            .and_then(|info| info.selected_entry())
+            .cloned()
+        else {
+            return Ok(());
+        };
+        match entry {
+            (key @ LibraryItemKey::Artist(_), _) => self
+                .tx_library_request
+                .send(LibraryRequest::GetSongs(key, mode))?,
+            song => self.enqueue_songs(vec![song], mode)?,
+        }
+        Ok(())
+    }
+
+    /// Append the top songs of the artist shown in the library to the queue.
+    fn enqueue_top_songs(&mut self) -> Result<()> {
+        if let Some(ArtistInfoContext {
+            info: Resolvable::Resolved(info),
+            ..
+        }) = &self.model.artist_info
+        {
+            let songs = info.top_songs.clone();
+            self.enqueue_songs(songs, EnqueueMode::Append)?;
+        }
+        Ok(())
+    }
+
+    fn toggle_star_selected(&mut self) -> Result<()> {
+        if let Some((key, _)) = self.model.library().selected_child() {
+            self.tx_library_request
+                .send(LibraryRequest::ToggleStar(key.clone()))?;
+        }
+        Ok(())
+    }
+
+    fn rate_selected(&mut self, rating: u8) -> Result<()> {
+        if let Some((key, _)) = self.model.library().selected_child() {
+            self.tx_library_request
+                .send(LibraryRequest::SetRating(key.clone(), rating))?;
+        }
+        Ok(())
+    }
+
+    /// Ask for the playlists to add the selected item to, the picker opens once they have
+    /// been fetched.
+    fn add_selected_to_playlist(&mut self) -> Result<()> {
+        if let Some((key, _)) = self.model.library().selected_child() {
+            self.playlist_target = Some(key.clone());
+            self.tx_library_request.send(LibraryRequest::GetPlaylists)?;
+        }
+        Ok(())
+    }
+
+    fn open_playlist_picker(&mut self, playlists: Vec<(Id, String)>) {
+        let (ids, names): (Vec<_>, Vec<_>) = std::iter::once((None, String::from("New playlist")))
+            .chain(playlists.into_iter().map(|(id, name)| (Some(id), name)))
+            .unzip();
+        self.model.picker = Some(Picker {
+            kind: PickerKind::Playlist,
+            title: String::from("Add to playlist"),
+            entries: names,
+            selected: 0,
+        });
+        self.playlists = ids;
+    }
+
+    fn select_playlist(&mut self, index: usize) -> Result<()> {
+        if let (Some(playlist_id), Some(key)) =
+            (self.playlists.get(index), self.playlist_target.take())
+        {
+            self.tx_library_request
+                .send(LibraryRequest::AddToPlaylist(playlist_id.clone(), key))?;
+        }
+        Ok(())
+    }
+
+    /// Remove the selected song from the playlist shown, or ask whether to delete the selected
+    /// playlist. Playlists are shared with other users, so deleting one is confirmed first.
+    fn remove_selected_from_playlist(&mut self) -> Result<()> {
+        let library = self.model.library();
+        match (&library.key, library.selected_child()) {
+            (LibraryItemKey::Playlist(playlist_id), Some(_)) => {
+                self.tx_library_request
+                    .send(LibraryRequest::RemoveFromPlaylist(
+                        playlist_id.clone(),
+                        library.selected,
+                    ))?;
+            }
+            (LibraryItemKey::Playlists, Some((LibraryItemKey::Playlist(playlist_id), item))) => {
+                let title = format!("Delete playlist {}?", item.name());
+                self.playlist_to_delete = Some(playlist_id.clone());
+                self.model.picker = Some(Picker {
+                    kind: PickerKind::DeletePlaylist,
+                    title,
+                    entries: vec![String::from("No"), String::from("Yes")],
+                    selected: 0,
+                });
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    fn delete_playlist(&mut self, confirmed: bool) -> Result<()> {
+        if let Some(playlist_id) = self.playlist_to_delete.take() {
+            if confirmed {
+                self.tx_library_request
+                    .send(LibraryRequest::DeletePlaylist(playlist_id))?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Move the selected song of the playlist shown. The selection moves along, the playlist
+    /// is shown again once the server has been updated.
+    fn move_selected_playlist_entry(&mut self, up: bool) -> Result<()> {
+        let library = self.model.library_mut();
+        let playlist_id = match &library.key {
+            LibraryItemKey::Playlist(playlist_id) => playlist_id.clone(),
+            _ => return Ok(()),
+        };
+        let from = library.selected;
+        let to = if up {
+            from.saturating_sub(1)
+        } else {
+            (from + 1).min(library.children.len().saturating_sub(1))
+        };
+        if from != to {
+            library.selected = to;
+            self.tx_library_request
+                .send(LibraryRequest::MovePlaylistEntry(playlist_id, from, to))?;
+        }
+        Ok(())
+    }
+
+    /// Take over the play queue stored on the server, unless songs have been enqueued since
+    /// startup. The current song is opened paused at the stored position.
+    fn restore_queue(&mut self, saved_queue: Option<SavedQueue>) -> Result<()> {
+        self.queue_restored = true;
+        let saved_queue = match saved_queue {
+            Some(saved_queue) if self.model.queue.entries().is_empty() => saved_queue,
+            _ => return Ok(()),
+        };
+        let origin = format!(
+            "saved by {} on {}",
+            saved_queue.changed_by,
+            saved_queue
+                .changed
+                .with_timezone(&chrono::Local)
+                .format("%Y-%m-%d %H:%M")
+        );
+        self.model.add_log(&format!(
+            "restored play queue of {} songs, {}",
+            saved_queue.entries.len(),
+            origin
+        ));
+        self.model.queue_origin = Some(origin);
+        self.model
+            .queue
+            .restore(saved_queue.entries, saved_queue.current);
+        self.model.queue_selected = self.model.queue.current_index();
+        if let Some(entry) = self.model.queue.current() {
+            self.tx_player_request
+                .send(PlayerRequest::Cue(entry.id.clone(), saved_queue.position))?;
+        }
+        Ok(())
+    }
+
+    /// Store the play queue on the server, with the position in the current song.
+    fn save_queue(&mut self) -> Result<()> {
+        if !self.queue_restored {
+            return Ok(());
+        }
+        let song_ids = self
+            .model
+            .queue
+            .entries()
+            .iter()
+            .map(|entry| entry.id.clone())
+            .collect();
+        let current = self.model.queue.current().map(|entry| entry.id.clone());
+        let position = match &self.model.playback.state {
+            PlayerState::Playing(id) | PlayerState::Paused(id) if Some(id) == current.as_ref() => {
+                self.model.playback.position
+            }
+            _ => Duration::ZERO,
+        };
+        self.tx_library_request
+            .send(LibraryRequest::SavePlayQueue(song_ids, current, position))?;
+        self.queue_saved = Instant::now();
+        Ok(())
+    }
+
+    /// The cover art to show: of the playing song, or else of the selected library item.
+    fn wanted_cover(&self) -> Option<Id> {
+        if self.model.graphics == Graphics::None {
+            return None;
+        }
+        let playing = !matches!(self.model.playback.state, PlayerState::Stopped);
+        let current = self.model.queue.current().filter(|_| playing);
+        match current {
+            Some(entry) => entry.song.cover_art.clone(),
+            None => self.model.selected_cover_art().cloned(),
+        }
+    }
+
+    /// Request the cover art to show if it changed.
+    fn update_cover(&mut self) -> Result<()> {
+        let wanted = self.wanted_cover();
+        if wanted.as_ref() == self.model.cover.as_ref().map(|(id, _)| id) {
+            return Ok(());
+        }
+        self.model.cover = wanted.map(|id| (id, Resolvable::Pending));
+        if let Some((id, _)) = &self.model.cover {
+            self.tx_library_request
+                .send(LibraryRequest::GetCoverArt(id.clone()))?;
+        }
+        self.redraw = true;
+        Ok(())
+    }
+
+    /// Request the lyrics of the playing song if the lyrics panel is open and the song changed.
+    fn update_lyrics(&mut self) -> Result<()> {
+        let Some(lyrics) = &mut self.model.lyrics else {
+            return Ok(());
+        };
+        let playing = match &self.model.playback.state {
+            PlayerState::Playing(id) | PlayerState::Paused(id) => Some(id),
+            PlayerState::Stopped => None,
+        };
+        if playing == lyrics.id.as_ref() {
+            return Ok(());
+        }
+        *lyrics = LyricsContext::new();
+        if let Some(id) = playing {
+            lyrics.id = Some(id.clone());
+            lyrics.lyrics = Resolvable::Pending;
+            self.tx_library_request
+                .send(LibraryRequest::GetLyrics(id.clone()))?;
+        }
+        self.redraw = true;
+        Ok(())
+    }
+
+    /// Scroll plain lyrics with the navigation keys.
+    fn scroll_lyrics(&mut self, code: KeyCode) {
+        let page_size = self.page_size();
+        if let Some(lyrics) = &mut self.model.lyrics {
+            if let Resolvable::Resolved(Some(text)) = &lyrics.lyrics {
+                if let Some(scroll) =
+                    navigate(Some(lyrics.scroll), text.lines.len(), page_size, code)
+                {
+                    lyrics.scroll = scroll;
+                }
+            }
+        }
+    }
+
+    /// Write the cover art as an image after a frame was drawn, for the Kitty and Sixel
+    /// protocols. Images stay on the screen, so they are only written when they change.
+    fn draw_cover_image(&mut self) -> Result<()> {
+        if !matches!(self.model.graphics, Graphics::Kitty | Graphics::Sixel) {
+            return Ok(());
+        }
+        let area = view::cover_area(self.terminal.size()?, &self.model);
+        let target = match (&self.model.cover, area) {
+            (Some((id, Resolvable::Resolved(cover))), Some(area)) => {
+                Some((id.clone(), area, cover))
+            }
+            _ => None,
+        };
+        let target_key = target.as_ref().map(|(id, area, _)| (id.clone(), *area));
+        if target_key == self.cover_drawn {
+            return Ok(());
+        }
+
+        if self.cover_drawn.is_some() {
+            match self.model.graphics {
+                Graphics::Kitty => write!(self.terminal.backend_mut(), "{}", KITTY_CLEAR)?,
+                // sixel images are only removed by overwriting them
+                _ => {
+                    self.terminal.clear()?;
+                    self.terminal.draw(|f| view::draw(f, &self.model))?;
+                }
+            }
+        }
+        if let Some((_, area, cover)) = target {
+            let image = cover.fit(
+                area.width as u32 * CELL_WIDTH,
+                area.height as u32 * CELL_HEIGHT,
+            );
+            let (cols, rows) = view::image_cells(image.width(), image.height());
+            let x = area.x + area.width.saturating_sub(cols) / 2;
+            let data = match self.model.graphics {
+                Graphics::Kitty => cover::kitty(&image, cols, rows)?,
+                _ => cover::sixel(&image),
+            };
+            let backend = self.terminal.backend_mut();
+            execute!(backend, crossterm::cursor::MoveTo(x, area.y))?;
+            write!(backend, "{}", data)?;
+        }
+        self.terminal.backend_mut().flush()?;
+        self.cover_drawn = target_key;
+        Ok(())
+    }
+
+    fn play_selected_queue_entry(&mut self) -> Result<()> {
+        if let Some(index) = self.model.queue_selected {
+            if let Some(entry) = self.model.queue.select(index) {
+                self.tx_player_request
+                    .send(PlayerRequest::Play(entry.id.clone()))?;
+            }
+        }
+        Ok(())
+    }
+
+    fn move_selected_queue_entry(&mut self, up: bool) {
+        if let Some(from) = self.model.queue_selected {
+            let to = if up {
+                from.saturating_sub(1)
+            } else {
+                (from + 1).min(self.model.queue.entries().len().saturating_sub(1))
+            };
+            self.model.queue.move_entry(from, to);
+            self.model.queue_selected = Some(to);
+        }
+    }
+
+    fn remove_selected_queue_entry(&mut self) {
+        if let Some(index) = self.model.queue_selected {
+            self.model.queue.remove(index);
+            let len = self.model.queue.entries().len();
+            self.model.queue_selected = if len == 0 {
+                None
+            } else {
+                Some(index.min(len - 1))
+            };
+        }
+    }
+
+    fn open_profile_picker(&mut self) {
+        self.model.picker = Some(Picker {
+            kind: PickerKind::Profile,
+            title: String::from("Profiles"),
+            entries: self.profiles.iter().map(|p| p.name.clone()).collect(),
+            selected: self.active_profile,
+        });
+    }
+
+    /// Connect to the server of another profile. Playback stops and the queue is cleared, as
+    /// its songs belong to the previous server.
+    fn switch_profile(&mut self, index: usize) -> Result<()> {
+        let profile = match self.profiles.get(index) {
+            Some(profile) => profile.clone(),
+            None => return Ok(()),
+        };
+        // stay with the current profile if the password cannot be read
+        let conn = match profile.connection(&SystemKeyring) {
+            Ok(conn) => conn,
+            Err(err) => {
+                self.model.add_log(&err.to_string());
+                return Ok(());
+            }
+        };
+        self.active_profile = index;
+        self.save_queue()?;
+        self.queue_restored = false;
+        self.model.queue_origin = None;
+        // the player gets the new connection once the library has connected
+        self.tx_player_request.send(PlayerRequest::Stop)?;
+        self.model.status = format!("[{}] connecting to: {}", profile.name, profile.url);
+        self.tx_library_request
+            .send(LibraryRequest::SwitchProfile(profile, conn))?;
+
+        self.model.queue.clear();
+        self.model.queue_selected = None;
+        self.open_library(LibraryItemKey::Root, None)
+    }
+
+    fn open_music_folder_picker(&mut self, music_folders: Vec<(Id, String)>, current: Option<Id>) {
+        let (ids, names): (Vec<_>, Vec<_>) = std::iter::once((None, String::from("All folders")))
+            .chain(music_folders.into_iter().map(|(id, name)| (Some(id), name)))
+            .unzip();
+        self.model.picker = Some(Picker {
+            kind: PickerKind::MusicFolder,
+            title: String::from("Music folders"),
+            selected: ids.iter().position(|id| *id == current).unwrap_or(0),
+            entries: names,
+        });
+        self.music_folders = ids;
+    }
+
+    /// Restrict the library to another music folder. The queue is kept, its songs are still
+    /// on the server.
+    fn select_music_folder(&mut self, index: usize) -> Result<()> {
+        if let Some(music_folder) = self.music_folders.get(index) {
+            self.tx_library_request
+                .send(LibraryRequest::SetMusicFolder(music_folder.clone()))?;
+            self.open_library(LibraryItemKey::Root, None)?;
+        }
+        Ok(())
+    }
+
+    fn handle_picker_key(&mut self, key: KeyEvent) -> Result<()> {
+        let page_size = self.page_size();
+        let picker = match &mut self.model.picker {
+            Some(picker) => picker,
+            None => return Ok(()),
+        };
+        match key.code {
+            KeyCode::Esc => self.model.picker = None,
+            KeyCode::Enter => {
+                let (kind, selected) = (picker.kind, picker.selected);
+                self.model.picker = None;
+                match kind {
+                    PickerKind::Profile => self.switch_profile(selected)?,
+                    PickerKind::MusicFolder => self.select_music_folder(selected)?,
+                    PickerKind::Playlist => self.select_playlist(selected)?,
+                    PickerKind::DeletePlaylist => self.delete_playlist(selected == 1)?,
+                }
+            }
+            code => {
+                if let Some(selected) =
+                    navigate(Some(picker.selected), picker.entries.len(), page_size, code)
+                {
+                    picker.selected = selected;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Send a search request for the current query, superseding all earlier ones.
+    fn update_search(&mut self) -> Result<()> {
+        self.model.search_id += 1;
+        let search_id = self.model.search_id;
+        if let Some(search) = self.model.search_mut() {
+            search.results = Resolvable::Pending;
+            search.selected = 0;
+            self.tx_library_request.send(LibraryRequest::FindEntries(
+                search_id,
+                search.find_type,
+                search.query.clone(),
+            ))?;
+        }
+        Ok(())
+    }
+
+    /// Open the selected search result in the library view, or play it if it is a song.
+    fn open_search_result(&mut self) -> Result<()> {
+        let key = match self.model.search().and_then(|s| s.selected_result()) {
+            Some((key, _)) => key.clone(),
+            None => return Ok(()),
+        };
+
+        if let LibraryItemKey::Song(_) = key {
+            self.tx_library_request
+                .send(LibraryRequest::GetSongs(key, EnqueueMode::Play))?;
+        } else {
+            self.open_library(key, None)?;
+        }
+        Ok(())
+    }
+
+    fn handle_search_key(&mut self, key: KeyEvent) -> Result<()> {
+        let page_size = self.page_size();
+        let search = match self.model.search_mut() {
+            Some(search) => search,
+            None => return Ok(()),
+        };
+        match key.code {
+            KeyCode::Esc => self.model.close_search(),
+            KeyCode::Enter => self.open_search_result()?,
+            KeyCode::Tab => {
+                search.find_type = search.find_type.next();
+                self.update_search()?;
+            }
+            KeyCode::Backspace => {
+                search.query.pop();
+                self.update_search()?;
+            }
+            KeyCode::Char(c) => {
+                search.query.push(c);
+                self.update_search()?;
+            }
+            code => {
+                if let Some(selected) =
+                    navigate(Some(search.selected), search.results.len(), page_size, code)
+                {
+                    search.selected = selected;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Handle a key press. Returns `false` if the UI should shut down.
+    fn handle_key(&mut self, key: KeyEvent) -> Result<bool> {
+        if self.model.picker.is_some() {
+            self.handle_picker_key(key)?;
+            return Ok(true);
+        }
+        if self.model.search().is_some() {
+            self.handle_search_key(key)?;
+            return Ok(true);
+        }
+
+        match key.code {
+            KeyCode::Char('q') => {
+                // shutdown
+                self.save_queue()?;
+                self.tx_library_request.send(LibraryRequest::Shutdown)?;
+                self.tx_player_request.send(PlayerRequest::Shutdown)?;
+                return Ok(false);
+            }
+            KeyCode::Char(' ') => {
+                self.tx_player_request.send(PlayerRequest::TogglePause)?;
+            }
+            KeyCode::Char('s') => {
+                self.tx_player_request.send(PlayerRequest::Stop)?;
+            }
+            KeyCode::Char('n') => {
+                self.play_next()?;
+            }
+            KeyCode::Char('p') => {
+                self.play_previous()?;
+            }
+            KeyCode::Char('c') => {
+                self.model.queue.clear();
+                self.model.queue_selected = None;
+                self.model.queue_origin = None;
+            }
+            KeyCode::Left => {
+                let position = self.model.playback.position.saturating_sub(SEEK_STEP);
+                self.tx_player_request.send(PlayerRequest::Seek(position))?;
+            }
+            KeyCode::Right => {
+                let position = self.model.playback.position + SEEK_STEP;
+                self.tx_player_request.send(PlayerRequest::Seek(position))?;
+            }
+            KeyCode::Char('/') => {
+                self.model.open_search();
+            }
+            KeyCode::Char('P') => {
+                self.open_profile_picker();
+            }
+            KeyCode::Char('L') => {
+                self.model.lyrics = match self.model.lyrics {
+                    Some(_) => None,
+                    None => Some(LyricsContext::new()),
+                };
+                // the lyrics take the place of the library and the artist info
+                if self.model.focus == Focus::ArtistInfo {
+                    self.model.focus = Focus::Library;
+                }
+            }
+            KeyCode::Char('F') => {
+                // the picker opens once the folders have been fetched
+                self.tx_library_request
+                    .send(LibraryRequest::GetMusicFolders)?;
+            }
+            KeyCode::Char('T') => {
+                self.enqueue_top_songs()?;
+            }
+            KeyCode::Tab => {
+                // the artist info panel is hidden by the lyrics
+                let artist_info = self.model.artist_info.is_some() && self.model.lyrics.is_none();
+                self.model.focus = match self.model.focus {
+                    Focus::Library if artist_info => Focus::ArtistInfo,
+                    Focus::Library | Focus::ArtistInfo => Focus::Queue,
+                    Focus::Queue => Focus::Library,
+                };
+            }
+            code => match self.model.focus {
+                // the lyrics panel takes the place of the library
+                Focus::Library if self.model.lyrics.is_some() => self.scroll_lyrics(code),
+                Focus::Library => match code {
+                    KeyCode::Enter => self.open_selected()?,
+                    KeyCode::Backspace => self.close_current()?,
+                    KeyCode::Char('a') => self.enqueue_selected(EnqueueMode::Append)?,
+                    KeyCode::Char('i') => self.enqueue_selected(EnqueueMode::Next)?,
+                    KeyCode::Char('r') => self.refresh_library()?,
+                    KeyCode::Char('*') => self.toggle_star_selected()?,
+                    KeyCode::Char(c @ '0'..='5') => self.rate_selected(c as u8 - b'0')?,
+                    KeyCode::Char('+') => self.add_selected_to_playlist()?,
+                    KeyCode::Char('d') | KeyCode::Delete => self.remove_selected_from_playlist()?,
+                    KeyCode::Char('K') => self.move_selected_playlist_entry(true)?,
+                    KeyCode::Char('J') => self.move_selected_playlist_entry(false)?,
+                    code => {
+                        let page_size = self.page_size();
+                        let library = self.model.library_mut();
+                        if let Some(selected) = navigate(
+                            Some(library.selected),
+                            library.children.len(),
+                            page_size,
+                            code,
+                        ) {
+                            library.selected = selected;
+                        }
+                        self.load_more()?;
+                    }
+                },
+                Focus::ArtistInfo => match code {
+                    KeyCode::Enter => self.open_selected_artist_info()?,
+                    KeyCode::Char('a') => self.enqueue_selected_artist_info(EnqueueMode::Append)?,
+                    KeyCode::Char('i') => self.enqueue_selected_artist_info(EnqueueMode::Next)?,
+                    code => {
+                        let page_size = self.page_size();
+                        if let Some(info) = &mut self.model.artist_info {
+                            if let Some(selected) =
+                                navigate(Some(info.selected), info.len(), page_size, code)
+                            {
+                                info.selected = selected;
+                            }
+                        }
+                    }
+                },
+                Focus::Queue => match code {
+                    KeyCode::Enter => self.play_selected_queue_entry()?,
+                    KeyCode::Char('d') | KeyCode::Delete => self.remove_selected_queue_entry(),
+                    KeyCode::Char('K') => self.move_selected_queue_entry(true),
+                    KeyCode::Char('J') => self.move_selected_queue_entry(false),
+                    code => {
+                        self.model.queue_selected = navigate(
+                            self.model.queue_selected,
+                            self.model.queue.entries().len(),
+                            self.page_size(),
+                            code,
+                        )
+                        .or(self.model.queue_selected);
+                    }
+                },
+            },
+        }
+        Ok(true)
+    }
+
+    fn handle_ui_event(&mut self, ui_event: UiEvent) -> Result<()> {
+        match ui_event {
+            UiEvent::LibraryGetChildrenComplete(key, node) => {
+                // ignore results for views which have been left in the meantime
+                if key == self.model.library().key {
+                    self.model.library_mut().resolve(node);
+                }
+            }
+            UiEvent::LibraryFindEntriesComplete(request_id, results) => {
+                // drop results of outdated searches
+                if request_id == self.model.search_id {
+                    if let Some(search) = self.model.search_mut() {
+                        search.results = Resolvable::Resolved(results);
+                    }
+                }
+            }
+            UiEvent::LibraryFindEntriesFailed(request_id, error) => {
+                if request_id == self.model.search_id {
+                    if let Some(search) = self.model.search_mut() {
+                        search.results = Resolvable::Error(error.clone());
+                        self.model.add_log(&format!("search failed: {}", error));
+                    }
+                }
+            }
+            UiEvent::LibraryGetSongsComplete(mode, songs) => {
+                self.enqueue_songs(songs, mode)?;
+            }
+            UiEvent::LibraryItemChanged(key, item) => {
+                self.model.update_item(&key, &item);
+            }
+            UiEvent::LibraryPlaylistsComplete(playlists) => {
+                self.open_playlist_picker(playlists);
+            }
+            UiEvent::LibraryCoverArtComplete(id, cover) => {
+                if let Some((cover_id, resolvable)) = &mut self.model.cover {
+                    if *cover_id == id {
+                        *resolvable = Resolvable::Resolved(cover);
+                    }
+                }
+            }
+            UiEvent::LibraryCoverArtFailed(id, msg) => {
+                if let Some((cover_id, resolvable)) = &mut self.model.cover {
+                    if *cover_id == id {
+                        *resolvable = Resolvable::Error(msg.clone());
+                    }
+                }
+                self.model.add_log(&format!("cover art failed: {}", msg));
+            }
+            UiEvent::LibraryArtistInfoComplete(id, info) => {
+                if let Some(artist_info) = &mut self.model.artist_info {
+                    if artist_info.id == id {
+                        artist_info.info = Resolvable::Resolved(info);
+                    }
+                }
+            }
+            UiEvent::LibraryArtistInfoFailed(id, msg) => {
+                if let Some(artist_info) = &mut self.model.artist_info {
+                    if artist_info.id == id {
+                        artist_info.info = Resolvable::Error(msg.clone());
+                    }
+                }
+                self.model.add_log(&format!("artist info failed: {}", msg));
+            }
+            UiEvent::LibraryLyricsComplete(id, text) => {
+                if let Some(lyrics) = &mut self.model.lyrics {
+                    if lyrics.id.as_ref() == Some(&id) {
+                        lyrics.lyrics = Resolvable::Resolved(text);
+                    }
+                }
+            }
+            UiEvent::LibraryLyricsFailed(id, msg) => {
+                if let Some(lyrics) = &mut self.model.lyrics {
+                    if lyrics.id.as_ref() == Some(&id) {
+                        lyrics.lyrics = Resolvable::Error(msg.clone());
+                    }
+                }
+                self.model.add_log(&format!("lyrics failed: {}", msg));
+            }
+            UiEvent::LibraryPlayQueueComplete(saved_queue) => {
+                self.restore_queue(saved_queue)?;
+            }
+            UiEvent::LibraryMusicFoldersComplete(music_folders, current) => {
+                self.open_music_folder_picker(music_folders, current);
+            }
+            UiEvent::LibraryRequestFailed(key, error) => {
+                if key == self.model.library().key {
+                    self.model.library_mut().children = Resolvable::Error(error.clone());
+                }
+                self.model
+                    .add_log(&format!("library request failed: {}", error));
+            }
+            UiEvent::AddLog(s) => {
+                self.model.add_log(&s);
+            }
+            UiEvent::SetStatus(s) => {
+                self.model.status = s;
+            }
+            UiEvent::LibraryConnected(conn) => {
+                self.tx_player_request
+                    .send(PlayerRequest::SetConnection(conn))?;
+            }
+            UiEvent::PlayerStateChanged(state) => {
+                match &state {
+                    PlayerState::Stopped => {
+                        self.model.playback.position = Duration::ZERO;
+                        self.model.playback.duration = None;
+                        self.model.playback.title = None;
+                    }
+                    PlayerState::Playing(id) | PlayerState::Paused(id) => {
+                        self.model.playback.title = self
+                            .model
+                            .queue
+                            .current()
+                            .filter(|entry| &entry.id == id)
+                            .map(|entry| entry.song.title.to_string());
+                    }
+                }
+                self.model.playback.state = state;
+            }
+            UiEvent::PlayerPosition(position, duration) => {
+                self.model.playback.position = position;
+                self.model.playback.duration = duration;
+            }
+            UiEvent::PlayerTrackEnded(_) => {
+                self.play_next()?;
+            }
+        }
+        Ok(())
+    }
+
+    fn play_next(&mut self) -> Result<()> {
+        if let Some(entry) = self.model.queue.next() {
+            self.tx_player_request
+                .send(PlayerRequest::Play(entry.id.clone()))?;
+        }
+        Ok(())
+    }
+
+    fn play_previous(&mut self) -> Result<()> {
+        if let Some(entry) = self.model.queue.previous() {
+            self.tx_player_request
+                .send(PlayerRequest::Play(entry.id.clone()))?;
+        }
+        Ok(())
     }
 
     pub fn run(&mut self) -> Result<()> {
@@ -96,38 +1054,44 @@ impl Ui {
                 match crossterm::event::read()? {
                     Event::Key(key) => {
                         // keyboard input
-                        if let KeyCode::Char('q') = key.code {
-                            // shutdown
-                            self.tx_library_request.send(LibraryRequest::Shutdown)?;
+                        if !self.handle_key(key)? {
                             return Ok(());
                         }
+                        self.redraw = true;
                     }
                     Event::Resize(_, _) => {
                         // resized terminal, redraw
                         self.redraw = true;
+                        self.cover_drawn = None;
                     }
                     _ => {}
                 }
             }
 
             while let Ok(ui_event) = self.rx_ui_event.try_recv() {
-                match ui_event {
-                    UiEvent::LibraryGetChildrenComplete(_view_id, children_result) => {
-                        self.set_library_view(children_result);
-                    }
-                    UiEvent::LibraryFindEntriesComplete(_, _) => todo!(),
-                    UiEvent::AddLog(s) => {
-                        self.add_log(&s);
-                    }
-                    UiEvent::SetStatus(s) => {
-                        self.set_status(&s);
-                    }
-                }
+                self.handle_ui_event(ui_event)?;
+                self.redraw = true;
+            }
+
+            if matches!(self.model.playback.state, PlayerState::Playing(_))
+                && self.queue_saved.elapsed() >= QUEUE_SAVE_INTERVAL
+            {
+                self.save_queue()?;
+            }
+
+            // animate the spinner while waiting for the library
+            self.model.tick = self.model.tick.wrapping_add(1);
+            if self.model.is_pending() {
                 self.redraw = true;
             }
 
+            self.update_cover()?;
+            self.update_lyrics()?;
+            self.update_artist_info()?;
+
             if self.redraw {
-                self.terminal.draw(|f| ui(f, &self.app_state))?;
+                self.terminal.draw(|f| view::draw(f, &self.model))?;
+                self.draw_cover_image()?;
                 self.redraw = false;
             }
 
@@ -138,6 +1102,9 @@ impl Ui {
 
 impl Drop for Ui {
     fn drop(&mut self) {
+        if self.model.graphics == Graphics::Kitty {
+            let _ = write!(self.terminal.backend_mut(), "{}", KITTY_CLEAR);
+        }
         disable_raw_mode().unwrap();
         execute!(
             self.terminal.backend_mut(),
@@ -149,53 +1116,21 @@ impl Drop for Ui {
     }
 }
 
-struct AppState {
-    status: String,
-    log: String,
-    library_view: Vec<UiLibraryItem>,
-}
-
-struct UiLibraryItem {
-    id: LibraryItemKey,
-    text: String,
-}
-
-fn ui<B: Backend>(f: &mut Frame<B>, app_state: &AppState) {
-    let size = f.size();
-
-    let chunks = Layout::default()
-        .direction(Direction::Vertical)
-        .margin(1)
-        .constraints(
-            [
-                Constraint::Percentage(10),
-                Constraint::Percentage(70),
-                Constraint::Percentage(20),
-            ]
-            .as_ref(),
-        )
-        .split(size);
-
-    let create_block = |title| {
-        Block::default().borders(Borders::ALL).title(Span::styled(
-            title,
-            Style::default().add_modifier(Modifier::BOLD),
-        ))
+/// Move a list selection according to a navigation key. Returns `None` for an empty list
+/// or keys which do not navigate.
+fn navigate(selected: Option<usize>, len: usize, page_size: usize, code: KeyCode) -> Option<usize> {
+    if len == 0 {
+        return None;
+    }
+    let selected = selected.unwrap_or(0);
+    let selected = match code {
+        KeyCode::Up => selected.saturating_sub(1),
+        KeyCode::Down => selected + 1,
+        KeyCode::PageUp => selected.saturating_sub(page_size),
+        KeyCode::PageDown => selected + page_size,
+        KeyCode::Home => 0,
+        KeyCode::End => len - 1,
+        _ => return None,
     };
-
-    let status = Paragraph::new(Span::raw(&app_state.status)).block(create_block("navicon"));
-    f.render_widget(status, chunks[0]);
-
-    let items: Vec<_> = app_state
-        .library_view
-        .iter()
-        .map(|item| ListItem::new(item.text.as_str()))
-        .collect();
-    let library_view = List::new(items).block(create_block("Library"));
-    f.render_widget(library_view, chunks[1]);
-
-    let log = Paragraph::new(Text::raw(&app_state.log))
-        .block(create_block("Log"))
-        .wrap(Wrap { trim: true });
-    f.render_widget(log, chunks[2]);
+    Some(selected.min(len - 1))
 }
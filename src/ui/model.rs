@@ -1,41 +1,395 @@
-use crate::library::{LibraryItem, LibraryItemKey};
+use std::time::Duration;
 
-struct Model {
-    context: Context,
-    log: String,
+use crate::{
+    cover::{Cover, Graphics},
+    library::{request::FindType, ArtistInfo, LibraryItem, LibraryItemKey},
+    lyrics::Lyrics,
+    player::PlayerState,
+    queue::Queue,
+    subsonic::Id,
+};
+
+use super::event::{LibraryNode, LibraryRequestResult};
+
+pub struct Model {
+    pub status: String,
+    pub log: String,
+    pub context: Context,
+    pub queue: Queue,
+    pub queue_selected: Option<usize>,
+    /// the client which saved the queue restored from the server, and when
+    pub queue_origin: Option<String>,
+    pub focus: Focus,
+    /// popup selection list, takes the keyboard input while open
+    pub picker: Option<Picker>,
+    pub playback: Playback,
+    /// the cover art shown, of the playing song or else of the selected item
+    pub cover: Option<(Id, Resolvable<Cover>)>,
+    pub graphics: Graphics,
+    /// the lyrics panel, shown in place of the library while open
+    pub lyrics: Option<LyricsContext>,
+    /// the panel beside the albums of an artist
+    pub artist_info: Option<ArtistInfoContext>,
+    // id of the latest search request
+    pub search_id: usize,
+    // number of UI loop iterations, drives animations
+    pub tick: usize,
 }
 
 impl Model {
     pub fn new() -> Model {
         Model {
-            context: Context::Library(LibraryContext {
-                this: None,
-                children: Resolvable::Pending,
-                parent: None,
-            }),
+            status: String::new(),
             log: String::new(),
+            context: Context::Library(LibraryContext::new(LibraryItemKey::Root)),
+            queue: Queue::new(),
+            queue_selected: None,
+            queue_origin: None,
+            focus: Focus::Library,
+            picker: None,
+            playback: Playback {
+                state: PlayerState::Stopped,
+                title: None,
+                position: Duration::ZERO,
+                duration: None,
+            },
+            cover: None,
+            graphics: Graphics::Blocks,
+            lyrics: None,
+            artist_info: None,
+            search_id: 0,
+            tick: 0,
+        }
+    }
+
+    pub fn add_log(&mut self, msg: &str) {
+        self.log.push_str(msg);
+        self.log.push('\n');
+    }
+
+    /// The library context, which is kept below an open search.
+    pub fn library(&self) -> &LibraryContext {
+        match &self.context {
+            Context::Library(library) | Context::Search(_, library) => library,
+        }
+    }
+
+    pub fn library_mut(&mut self) -> &mut LibraryContext {
+        match &mut self.context {
+            Context::Library(library) | Context::Search(_, library) => library,
+        }
+    }
+
+    pub fn search(&self) -> Option<&SearchContext> {
+        match &self.context {
+            Context::Search(search, _) => Some(search),
+            Context::Library(_) => None,
+        }
+    }
+
+    pub fn search_mut(&mut self) -> Option<&mut SearchContext> {
+        match &mut self.context {
+            Context::Search(search, _) => Some(search),
+            Context::Library(_) => None,
+        }
+    }
+
+    pub fn open_search(&mut self) {
+        let context = std::mem::replace(
+            &mut self.context,
+            Context::Library(LibraryContext::new(LibraryItemKey::Root)),
+        );
+        self.context = match context {
+            Context::Library(library) => Context::Search(SearchContext::new(), library),
+            search => search,
+        };
+    }
+
+    pub fn close_search(&mut self) {
+        let context = std::mem::replace(
+            &mut self.context,
+            Context::Library(LibraryContext::new(LibraryItemKey::Root)),
+        );
+        self.context = match context {
+            Context::Search(_, library) => Context::Library(library),
+            library => library,
+        };
+    }
+
+    /// Show a changed item wherever it is listed.
+    pub fn update_item(&mut self, key: &LibraryItemKey, item: &LibraryItem) {
+        let (library, search) = match &mut self.context {
+            Context::Library(library) => (library, None),
+            Context::Search(search, library) => (library, Some(search)),
+        };
+        if library.key == *key {
+            library.this = Some(item.clone());
+        }
+        let lists =
+            std::iter::once(&mut library.children).chain(search.map(|search| &mut search.results));
+        for list in lists {
+            if let Resolvable::Resolved(entries) = list {
+                for (_, entry_item) in entries.iter_mut().filter(|(k, _)| k == key) {
+                    *entry_item = item.clone();
+                }
+            }
+        }
+        if let Some(ArtistInfoContext {
+            info: Resolvable::Resolved(info),
+            ..
+        }) = &mut self.artist_info
+        {
+            let entries = info.similar.iter_mut().chain(&mut info.top_songs);
+            for (_, entry_item) in entries.filter(|(k, _)| k == key) {
+                *entry_item = item.clone();
+            }
+        }
+        if let (LibraryItemKey::Song(id), LibraryItem::Song(song)) = (key, item) {
+            self.queue.update_song(id, song);
+        }
+    }
+
+    /// Whether the visible state is waiting for the library.
+    pub fn is_pending(&self) -> bool {
+        let cover_pending = matches!(&self.cover, Some((_, cover)) if cover.is_pending());
+        let lyrics_pending = matches!(&self.lyrics, Some(lyrics) if lyrics.lyrics.is_pending());
+        let info_pending = matches!(&self.artist_info, Some(info) if info.info.is_pending());
+        cover_pending
+            || lyrics_pending
+            || info_pending
+            || match &self.context {
+                Context::Library(library) => library.children.is_pending(),
+                Context::Search(search, _) => search.results.is_pending(),
+            }
+    }
+
+    /// The cover art of the selected library item or search result.
+    pub fn selected_cover_art(&self) -> Option<&Id> {
+        let selected = match &self.context {
+            Context::Library(library) => library.selected_child(),
+            Context::Search(search, _) => search.selected_result(),
+        };
+        match selected {
+            Some((_, LibraryItem::Album(album))) => album.cover_art.as_ref(),
+            Some((_, LibraryItem::Song(song))) => song.cover_art.as_ref(),
+            _ => None,
         }
     }
 }
 
-enum Context {
+pub enum Context {
     Library(LibraryContext),
-    Search(SearchContext),
+    /// a search, with the library context to return to
+    Search(SearchContext, LibraryContext),
+}
+
+pub struct LibraryContext {
+    pub key: LibraryItemKey,
+    pub this: Option<LibraryItem>,
+    pub children: Resolvable<LibraryRequestResult>,
+    pub parent: Option<LibraryItemKey>,
+    pub selected: usize,
+    // child to select once the children are resolved
+    pub select_key: Option<LibraryItemKey>,
+    /// whether further pages of children can be requested
+    pub more: bool,
 }
 
-struct LibraryContext {
-    this: Option<LibraryItem>,
-    children: Resolvable<Vec<LibraryItem>>,
-    parent: Option<LibraryItemKey>,
+impl LibraryContext {
+    pub fn new(key: LibraryItemKey) -> LibraryContext {
+        LibraryContext {
+            key,
+            this: None,
+            children: Resolvable::Pending,
+            parent: None,
+            selected: 0,
+            select_key: None,
+            more: false,
+        }
+    }
+
+    pub fn resolve(&mut self, node: LibraryNode) {
+        let mut children = node.children;
+        // artists come unordered from the cache and are sorted below the virtual nodes, albums
+        // and songs keep the server order
+        if self.key == LibraryItemKey::Root {
+            children.sort_by_key(|(key, item)| match key {
+                LibraryItemKey::Artist(_) => Some(item.to_string()),
+                _ => None,
+            });
+        }
+
+        // a further page keeps the selection, as the previous children are kept in order
+        if !matches!(self.children, Resolvable::Resolved(_)) {
+            self.selected = self
+                .select_key
+                .take()
+                .and_then(|select_key| children.iter().position(|(key, _)| *key == select_key))
+                .unwrap_or(0);
+        }
+        self.this = node.this;
+        // the parent set when descending is kept if the library knows none
+        if node.parent.is_some() {
+            self.parent = node.parent;
+        }
+        self.more = node.more;
+        self.children = Resolvable::Resolved(children);
+    }
+
+    pub fn selected_child(&self) -> Option<&(LibraryItemKey, LibraryItem)> {
+        match &self.children {
+            Resolvable::Resolved(children) => children.get(self.selected),
+            _ => None,
+        }
+    }
 }
 
-struct SearchContext {
-    query: String,
-    results: Resolvable<Vec<LibraryItem>>,
+pub struct SearchContext {
+    pub query: String,
+    pub find_type: FindType,
+    pub results: Resolvable<LibraryRequestResult>,
+    pub selected: usize,
 }
 
-enum Resolvable<T> {
+impl SearchContext {
+    pub fn new() -> SearchContext {
+        SearchContext {
+            query: String::new(),
+            find_type: FindType::Any,
+            results: Resolvable::Resolved(vec![]),
+            selected: 0,
+        }
+    }
+
+    pub fn selected_result(&self) -> Option<&(LibraryItemKey, LibraryItem)> {
+        match &self.results {
+            Resolvable::Resolved(results) => results.get(self.selected),
+            _ => None,
+        }
+    }
+}
+
+/// What a picker selects.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum PickerKind {
+    Profile,
+    MusicFolder,
+    Playlist,
+    /// confirm deleting a playlist from the server
+    DeletePlaylist,
+}
+
+/// The lyrics of the playing song.
+pub struct LyricsContext {
+    /// the song, `None` while nothing is playing
+    pub id: Option<Id>,
+    pub lyrics: Resolvable<Option<Lyrics>>,
+    /// first visible line of plain lyrics, synced lyrics follow the playback position
+    pub scroll: usize,
+}
+
+impl LyricsContext {
+    pub fn new() -> LyricsContext {
+        LyricsContext {
+            id: None,
+            lyrics: Resolvable::Resolved(None),
+            scroll: 0,
+        }
+    }
+}
+
+/// The biography, similar artists and top songs of the artist shown in the library.
+pub struct ArtistInfoContext {
+    pub id: Id,
+    pub info: Resolvable<ArtistInfo>,
+    /// selected entry, counting the similar artists first and then the top songs
+    pub selected: usize,
+}
+
+impl ArtistInfoContext {
+    pub fn new(id: Id) -> ArtistInfoContext {
+        ArtistInfoContext {
+            id,
+            info: Resolvable::Pending,
+            selected: 0,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        match &self.info {
+            Resolvable::Resolved(info) => info.similar.len() + info.top_songs.len(),
+            _ => 0,
+        }
+    }
+
+    pub fn selected_entry(&self) -> Option<&(LibraryItemKey, LibraryItem)> {
+        match &self.info {
+            Resolvable::Resolved(info) => info.entries().nth(self.selected),
+            _ => None,
+        }
+    }
+}
+
+pub struct Picker {
+    pub kind: PickerKind,
+    pub title: String,
+    pub entries: Vec<String>,
+    pub selected: usize,
+}
+
+pub enum Resolvable<T> {
     Pending,
     Resolved(T),
-    Error,
+    Error(String),
+}
+
+impl<T> Resolvable<T> {
+    pub fn is_pending(&self) -> bool {
+        matches!(self, Resolvable::Pending)
+    }
+}
+
+impl<T> Resolvable<Vec<T>> {
+    /// Number of resolved entries, zero if not resolved.
+    pub fn len(&self) -> usize {
+        match self {
+            Resolvable::Resolved(entries) => entries.len(),
+            _ => 0,
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Focus {
+    Library,
+    /// the artist info panel beside the library
+    ArtistInfo,
+    Queue,
+}
+
+pub struct Playback {
+    pub state: PlayerState,
+    pub title: Option<String>,
+    pub position: Duration,
+    pub duration: Option<Duration>,
+}
+
+impl std::fmt::Display for Playback {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let (symbol, id) = match &self.state {
+            PlayerState::Stopped => return write!(f, "stopped"),
+            PlayerState::Playing(id) => (">", id),
+            PlayerState::Paused(id) => ("||", id),
+        };
+        write!(
+            f,
+            "{} {} [{}:{:02} / {}:{:02}]",
+            symbol,
+            self.title.as_ref().unwrap_or(id),
+            self.position.as_secs() / 60,
+            self.position.as_secs() % 60,
+            self.duration.unwrap_or_default().as_secs() / 60,
+            self.duration.unwrap_or_default().as_secs() % 60
+        )
+    }
 }
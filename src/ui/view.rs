@@ -0,0 +1,573 @@
+use tui::{
+    backend::Backend,
+    layout::{Alignment, Constraint, Direction, Layout, Rect},
+    style::{Modifier, Style},
+    text::{Span, Spans, Text},
+    widgets::{Block, Borders, Clear, List, ListItem, ListState, Paragraph, Wrap},
+    Frame,
+};
+
+use crate::{
+    cover::{self, Graphics, CELL_HEIGHT, CELL_WIDTH},
+    library::{LibraryItem, LibraryItemKey},
+};
+
+use super::model::{
+    ArtistInfoContext, Focus, LibraryContext, LyricsContext, Model, Picker, Resolvable,
+    SearchContext,
+};
+
+const SPINNER: [&str; 4] = ["|", "/", "-", "\\"];
+
+struct Areas {
+    status: Rect,
+    library: Rect,
+    queue: Rect,
+    log: Rect,
+}
+
+fn layout(size: Rect) -> Areas {
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .margin(1)
+        .constraints(
+            [
+                Constraint::Length(4),
+                Constraint::Percentage(70),
+                Constraint::Percentage(20),
+            ]
+            .as_ref(),
+        )
+        .split(size);
+
+    let browse_chunks = Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints([Constraint::Percentage(60), Constraint::Percentage(40)].as_ref())
+        .split(chunks[1]);
+
+    Areas {
+        status: chunks[0],
+        library: browse_chunks[0],
+        queue: browse_chunks[1],
+        log: chunks[2],
+    }
+}
+
+/// Split the library area into the library and the artist info panel beside it.
+fn library_layout(library: Rect, model: &Model) -> (Rect, Option<Rect>) {
+    if model.artist_info.is_none() {
+        return (library, None);
+    }
+    let chunks = Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints([Constraint::Percentage(50), Constraint::Percentage(50)].as_ref())
+        .split(library);
+    (chunks[0], Some(chunks[1]))
+}
+
+/// Split the queue area into the cover art above and the queue below. Cells are about twice as
+/// high as wide, so a square cover takes half as many rows as columns.
+fn queue_layout(queue: Rect, model: &Model) -> (Option<Rect>, Rect) {
+    if model.cover.is_none() {
+        return (None, queue);
+    }
+    let height = (queue.width.saturating_sub(2) / 2 + 2).min(queue.height / 2);
+    let cover = Rect { height, ..queue };
+    let queue = Rect {
+        y: queue.y + height,
+        height: queue.height - height,
+        ..queue
+    };
+    (Some(cover), queue)
+}
+
+/// The area inside the cover art block, `None` if no cover art is shown.
+pub fn cover_area(size: Rect, model: &Model) -> Option<Rect> {
+    queue_layout(layout(size).queue, model)
+        .0
+        .map(|area| create_block("").inner(area))
+}
+
+/// Number of columns and rows taken by an image of the given size in pixels.
+pub fn image_cells(width: u32, height: u32) -> (u16, u16) {
+    (
+        width.div_ceil(CELL_WIDTH) as u16,
+        height.div_ceil(CELL_HEIGHT) as u16,
+    )
+}
+
+/// Number of list entries visible at once for the given terminal size.
+pub fn page_size(size: Rect) -> usize {
+    (layout(size).library.height.saturating_sub(2) as usize).max(1)
+}
+
+pub fn draw<B: Backend>(f: &mut Frame<B>, model: &Model) {
+    let areas = layout(f.size());
+
+    let status = Paragraph::new(vec![
+        Spans::from(Span::raw(&model.status)),
+        Spans::from(Span::raw(model.playback.to_string())),
+    ])
+    .block(create_block("navicon"));
+    f.render_widget(status, areas.status);
+
+    if let Some(search) = model.search() {
+        draw_search(f, areas.library, search, model.tick);
+    } else if let Some(lyrics) = &model.lyrics {
+        draw_lyrics(f, areas.library, lyrics, model);
+    } else {
+        let (library_area, info_area) = library_layout(areas.library, model);
+        draw_library(
+            f,
+            library_area,
+            model.library(),
+            model.focus == Focus::Library,
+            model.tick,
+        );
+        if let (Some(area), Some(info)) = (info_area, &model.artist_info) {
+            draw_artist_info(f, area, info, model.focus == Focus::ArtistInfo, model.tick);
+        }
+    }
+
+    let (cover_area, queue_area) = queue_layout(areas.queue, model);
+    if let Some(cover_area) = cover_area {
+        draw_cover(f, cover_area, model, model.tick);
+    }
+    draw_queue(f, queue_area, model);
+
+    let log = Paragraph::new(Text::raw(&model.log))
+        .block(create_block("Log"))
+        .wrap(Wrap { trim: true });
+    f.render_widget(log, areas.log);
+
+    if let Some(picker) = &model.picker {
+        draw_picker(f, areas.library, picker);
+    }
+}
+
+fn create_block(title: &str) -> Block<'_> {
+    Block::default().borders(Borders::ALL).title(Span::styled(
+        title,
+        Style::default().add_modifier(Modifier::BOLD),
+    ))
+}
+
+fn highlight_style(focus: bool) -> Style {
+    if focus {
+        Style::default().add_modifier(Modifier::REVERSED)
+    } else {
+        Style::default()
+    }
+}
+
+/// List entries for a value which is not resolved yet.
+fn unresolved_items<T>(value: &Resolvable<T>, tick: usize) -> Option<Vec<ListItem<'_>>> {
+    match value {
+        Resolvable::Pending => Some(vec![ListItem::new(format!(
+            "loading {}",
+            SPINNER[tick % SPINNER.len()]
+        ))]),
+        Resolvable::Error(error) => Some(vec![ListItem::new(format!("error: {}", error))
+            .style(Style::default().add_modifier(Modifier::ITALIC))]),
+        Resolvable::Resolved(_) => None,
+    }
+}
+
+fn draw_list<B: Backend>(
+    f: &mut Frame<B>,
+    area: Rect,
+    title: &str,
+    items: Vec<ListItem<'_>>,
+    selected: Option<usize>,
+    focus: bool,
+) {
+    let mut state = ListState::default();
+    state.select(selected);
+    let list = List::new(items)
+        .block(create_block(title))
+        .highlight_style(highlight_style(focus));
+    f.render_stateful_widget(list, area, &mut state);
+}
+
+fn draw_library<B: Backend>(
+    f: &mut Frame<B>,
+    area: Rect,
+    library: &LibraryContext,
+    focus: bool,
+    tick: usize,
+) {
+    let title = match &library.this {
+        Some(this) => format!("Library / {}", this),
+        None => String::from("Library"),
+    };
+
+    let (items, selected) = match unresolved_items(&library.children, tick) {
+        Some(mut items) => {
+            if let Resolvable::Error(_) = library.children {
+                items.push(ListItem::new("press r to retry"));
+            }
+            (items, None)
+        }
+        None => (
+            library_items(&library.children),
+            Some(library.selected).filter(|_| library.children.len() > 0),
+        ),
+    };
+    draw_list(f, area, &title, items, selected, focus);
+}
+
+fn library_items(children: &Resolvable<Vec<(LibraryItemKey, LibraryItem)>>) -> Vec<ListItem<'_>> {
+    match children {
+        Resolvable::Resolved(children) => children
+            .iter()
+            .map(|(_, item)| ListItem::new(item.to_string()))
+            .collect(),
+        _ => vec![],
+    }
+}
+
+/// Draw the biography of an artist above its similar artists and top songs.
+fn draw_artist_info<B: Backend>(
+    f: &mut Frame<B>,
+    area: Rect,
+    artist_info: &ArtistInfoContext,
+    focus: bool,
+    tick: usize,
+) {
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Percentage(40), Constraint::Percentage(60)].as_ref())
+        .split(area);
+
+    let (biography, items, selected) = match &artist_info.info {
+        Resolvable::Resolved(info) => {
+            let mut biography = Text::raw(info.biography.as_deref().unwrap_or("no biography"));
+            if let Some(mbid) = &info.music_brainz_id {
+                biography.extend(Text::raw(format!("\nMusicBrainz: {}", mbid)));
+            }
+
+            // headings are not selectable, the selection skips them
+            let mut items = vec![];
+            let mut selected = artist_info.selected;
+            let sections = [
+                ("Similar artists", &info.similar),
+                ("Top songs (T to enqueue)", &info.top_songs),
+            ];
+            for (i, (heading, entries)) in sections.into_iter().enumerate() {
+                if entries.is_empty() {
+                    continue;
+                }
+                items.push(ListItem::new(Span::styled(
+                    heading,
+                    Style::default().add_modifier(Modifier::BOLD),
+                )));
+                if i == 0 || artist_info.selected >= info.similar.len() {
+                    selected += 1;
+                }
+                items.extend(
+                    entries
+                        .iter()
+                        .map(|(_, item)| ListItem::new(item.to_string())),
+                );
+            }
+            let selected = Some(selected).filter(|_| artist_info.len() > 0);
+            (biography, items, selected)
+        }
+        unresolved => (
+            Text::raw(""),
+            unresolved_items(unresolved, tick).unwrap_or_default(),
+            None,
+        ),
+    };
+
+    let biography = Paragraph::new(biography)
+        .block(create_block("Artist info"))
+        .wrap(Wrap { trim: true });
+    f.render_widget(biography, chunks[0]);
+    draw_list(f, chunks[1], "Related", items, selected, focus);
+}
+
+fn draw_search<B: Backend>(f: &mut Frame<B>, area: Rect, search: &SearchContext, tick: usize) {
+    let title = format!("Search [{}]: {}_", search.find_type, search.query);
+    let (items, selected) = match unresolved_items(&search.results, tick) {
+        Some(items) => (items, None),
+        None => search_items(search),
+    };
+    draw_list(f, area, &title, items, selected, true);
+}
+
+/// List entries for the search results, grouped by item type. Returns the entries and the
+/// index of the entry of the selected result.
+fn search_items(search: &SearchContext) -> (Vec<ListItem<'_>>, Option<usize>) {
+    let results = match &search.results {
+        Resolvable::Resolved(results) => results,
+        _ => return (vec![], None),
+    };
+
+    let group = |key: &LibraryItemKey| match key {
+        LibraryItemKey::Artist(_) => "Artists",
+        LibraryItemKey::Album(_) => "Albums",
+        _ => "Songs",
+    };
+
+    let mut items = vec![];
+    let mut selected_item = None;
+    let mut current_group = None;
+    for (i, (key, item)) in results.iter().enumerate() {
+        if current_group != Some(group(key)) {
+            current_group = Some(group(key));
+            items.push(
+                ListItem::new(group(key)).style(Style::default().add_modifier(Modifier::BOLD)),
+            );
+        }
+        if i == search.selected {
+            selected_item = Some(items.len());
+        }
+        items.push(ListItem::new(format!("  {}", item)));
+    }
+    (items, selected_item)
+}
+
+fn draw_queue<B: Backend>(f: &mut Frame<B>, area: Rect, model: &Model) {
+    let items: Vec<_> = model
+        .queue
+        .entries()
+        .iter()
+        .enumerate()
+        .map(|(i, entry)| {
+            if Some(i) == model.queue.current_index() {
+                ListItem::new(format!("> {}", entry.song))
+                    .style(Style::default().add_modifier(Modifier::BOLD))
+            } else {
+                ListItem::new(format!("  {}", entry.song))
+            }
+        })
+        .collect();
+    let title = match &model.queue_origin {
+        Some(origin) => format!("Queue ({})", origin),
+        None => String::from("Queue"),
+    };
+    draw_list(
+        f,
+        area,
+        &title,
+        items,
+        model.queue_selected,
+        model.focus == Focus::Queue,
+    );
+}
+
+/// Draw the lyrics panel. The current line of synced lyrics is highlighted and kept in the
+/// middle of the panel.
+fn draw_lyrics<B: Backend>(f: &mut Frame<B>, area: Rect, lyrics: &LyricsContext, model: &Model) {
+    let (title, text, scroll) = match (&lyrics.id, &lyrics.lyrics) {
+        (None, _) => ("Lyrics", Text::raw("nothing playing"), 0),
+        (Some(_), Resolvable::Pending) => (
+            "Lyrics",
+            Text::raw(format!("loading {}", SPINNER[model.tick % SPINNER.len()])),
+            0,
+        ),
+        (Some(_), Resolvable::Error(error)) => {
+            ("Lyrics", Text::raw(format!("error: {}", error)), 0)
+        }
+        (Some(_), Resolvable::Resolved(None)) => ("Lyrics", Text::raw("no lyrics"), 0),
+        (Some(_), Resolvable::Resolved(Some(lyrics_text))) => {
+            let current = lyrics_text.current_line(model.playback.position);
+            let lines: Vec<_> = lyrics_text
+                .lines
+                .iter()
+                .enumerate()
+                .map(|(i, line)| {
+                    let style = if Some(i) == current {
+                        Style::default().add_modifier(Modifier::BOLD | Modifier::REVERSED)
+                    } else {
+                        Style::default()
+                    };
+                    Spans::from(Span::styled(line.text.as_str(), style))
+                })
+                .collect();
+            let height = area.height.saturating_sub(2) as usize;
+            let scroll = if lyrics_text.synced {
+                current.unwrap_or(0).saturating_sub(height / 2)
+            } else {
+                lyrics.scroll
+            };
+            let title = if lyrics_text.synced {
+                "Lyrics (synced)"
+            } else {
+                "Lyrics"
+            };
+            (title, Text::from(lines), scroll)
+        }
+    };
+    let paragraph = Paragraph::new(text)
+        .block(create_block(title))
+        .scroll((scroll.min(u16::MAX as usize) as u16, 0));
+    f.render_widget(paragraph, area);
+}
+
+/// Draw the cover art block. Images for the Kitty and Sixel protocols are written by the UI
+/// after the frame, the block is left empty for them.
+fn draw_cover<B: Backend>(f: &mut Frame<B>, area: Rect, model: &Model, tick: usize) {
+    let block = create_block("Cover");
+    let inner = block.inner(area);
+    f.render_widget(block, area);
+
+    let text = match &model.cover {
+        Some((_, Resolvable::Resolved(cover))) if model.graphics == Graphics::Blocks => {
+            let image = cover.fit(inner.width as u32, inner.height as u32 * 2);
+            Text::from(cover::half_blocks(&image))
+        }
+        Some((_, Resolvable::Resolved(_))) | None => return,
+        Some((_, Resolvable::Pending)) => {
+            Text::raw(format!("loading {}", SPINNER[tick % SPINNER.len()]))
+        }
+        Some((_, Resolvable::Error(_))) => Text::raw("no cover art"),
+    };
+    f.render_widget(Paragraph::new(text).alignment(Alignment::Center), inner);
+}
+
+/// Draw the picker as a popup in the middle of `area`.
+fn draw_picker<B: Backend>(f: &mut Frame<B>, area: Rect, picker: &Picker) {
+    let width = picker
+        .entries
+        .iter()
+        .map(|entry| entry.chars().count())
+        .chain(std::iter::once(picker.title.chars().count()))
+        .max()
+        .unwrap_or_default() as u16
+        + 4;
+    let height = picker.entries.len() as u16 + 2;
+    let popup = Rect {
+        x: area.x + area.width.saturating_sub(width) / 2,
+        y: area.y + area.height.saturating_sub(height) / 2,
+        width: width.min(area.width),
+        height: height.min(area.height),
+    };
+
+    let items = picker
+        .entries
+        .iter()
+        .map(|entry| ListItem::new(entry.as_str()))
+        .collect();
+    f.render_widget(Clear, popup);
+    draw_list(f, popup, &picker.title, items, Some(picker.selected), true);
+}
+
+#[cfg(test)]
+mod tests {
+    use tui::{backend::TestBackend, buffer::Buffer, Terminal};
+
+    use super::draw;
+    use crate::{
+        library::{Artist, LibraryItem, LibraryItemKey, Marks},
+        ui::{
+            event::LibraryNode,
+            model::{Context, Model, Picker, PickerKind, Resolvable, SearchContext},
+        },
+    };
+
+    fn render(model: &Model) -> Buffer {
+        let mut terminal = Terminal::new(TestBackend::new(60, 20)).unwrap();
+        terminal.draw(|f| draw(f, model)).unwrap();
+        terminal.backend().buffer().clone()
+    }
+
+    fn contains_row(buffer: &Buffer, y: u16, text: &str) -> bool {
+        let line: String = (0..buffer.area.width)
+            .map(|x| buffer.get(x, y).symbol.as_str())
+            .collect();
+        line.contains(text)
+    }
+
+    fn contains(buffer: &Buffer, text: &str) -> bool {
+        (0..buffer.area.height).any(|y| contains_row(buffer, y, text))
+    }
+
+    fn artist(name: &str) -> LibraryItem {
+        LibraryItem::Artist(Artist {
+            name: name.into(),
+            marks: Marks::default(),
+        })
+    }
+
+    #[test]
+    fn library_pending() {
+        let model = Model::new();
+        let buffer = render(&model);
+        assert!(contains(&buffer, "Library"));
+        assert!(contains(&buffer, "loading"));
+    }
+
+    #[test]
+    fn library_resolved() {
+        let mut model = Model::new();
+        model.library_mut().resolve(LibraryNode {
+            this: None,
+            parent: None,
+            children: vec![
+                (LibraryItemKey::Artist("2".into()), artist("Queen")),
+                (LibraryItemKey::Artist("1".into()), artist("ABBA")),
+            ],
+            more: false,
+        });
+        model.library_mut().selected = 1;
+
+        let buffer = render(&model);
+        assert!(!contains(&buffer, "loading"));
+        assert!(contains(&buffer, "ABBA"));
+        // children are sorted, the selected entry is highlighted
+        let y = (0..buffer.area.height)
+            .find(|y| buffer.get(2, *y).symbol == "Q")
+            .unwrap();
+        assert!(buffer.get(2, y - 1).symbol == "A");
+        assert_eq!(buffer.get(2, y).modifier, tui::style::Modifier::REVERSED);
+    }
+
+    #[test]
+    fn library_error() {
+        let mut model = Model::new();
+        model.library_mut().children = Resolvable::Error(String::from("not found"));
+
+        let buffer = render(&model);
+        assert!(contains(&buffer, "error: not found"));
+        assert!(contains(&buffer, "press r to retry"));
+    }
+
+    #[test]
+    fn picker() {
+        let mut model = Model::new();
+        model.picker = Some(Picker {
+            kind: PickerKind::Profile,
+            title: String::from("Profiles"),
+            entries: vec![String::from("home"), String::from("office")],
+            selected: 1,
+        });
+
+        let buffer = render(&model);
+        assert!(contains(&buffer, "Profiles"));
+        let y = (0..buffer.area.height)
+            .find(|y| contains_row(&buffer, *y, "office"))
+            .unwrap();
+        assert!(contains_row(&buffer, y - 1, "home"));
+    }
+
+    #[test]
+    fn search_grouped() {
+        let mut model = Model::new();
+        let mut search = SearchContext::new();
+        search.query = String::from("que");
+        search.results =
+            Resolvable::Resolved(vec![(LibraryItemKey::Artist("2".into()), artist("Queen"))]);
+        let library = match model.context {
+            Context::Library(library) => library,
+            Context::Search(_, library) => library,
+        };
+        model.context = Context::Search(search, library);
+
+        let buffer = render(&model);
+        assert!(contains(&buffer, "Search [all]: que_"));
+        assert!(contains(&buffer, "Artists"));
+        assert!(contains(&buffer, "  Queen"));
+    }
+}